@@ -0,0 +1,65 @@
+use std::hint::black_box;
+
+use conutils::{Arc, BiasedArc, BiasedRc};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const CLONES: usize = 1000;
+
+fn owner_thread(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clone_drop_owner_thread");
+
+    let arc = Arc::new(0u64);
+    group.bench_function("Arc", |b| {
+        b.iter(|| {
+            for _ in 0..CLONES {
+                black_box(arc.clone());
+            }
+        })
+    });
+
+    let rc = BiasedRc::new(0u64);
+    group.bench_function("BiasedRc", |b| {
+        b.iter(|| {
+            for _ in 0..CLONES {
+                black_box(rc.clone());
+            }
+        })
+    });
+
+    group.finish();
+}
+
+fn other_thread(c: &mut Criterion) {
+    let mut group = c.benchmark_group("clone_drop_other_thread");
+
+    let arc = Arc::new(0u64);
+    group.bench_function("Arc", |b| {
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                b.iter(|| {
+                    for _ in 0..CLONES {
+                        black_box(arc.clone());
+                    }
+                })
+            });
+        })
+    });
+
+    let shared = BiasedArc::new(0u64);
+    group.bench_function("BiasedArc", |b| {
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                b.iter(|| {
+                    for _ in 0..CLONES {
+                        black_box(shared.clone());
+                    }
+                })
+            });
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, owner_thread, other_thread);
+criterion_main!(benches);
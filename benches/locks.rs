@@ -0,0 +1,58 @@
+use std::thread;
+
+use conutils::{ClhLock, McsLock, Mutex, SpinLock, TicketLock};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const THREADS: [usize; 4] = [1, 2, 4, 8];
+const ITERATIONS: usize = 1000;
+
+/// Each of `threads` threads increments the counter `ITERATIONS` times.
+fn contend(threads: usize, lock: impl Fn() + Sync) {
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..ITERATIONS {
+                    lock();
+                }
+            });
+        }
+    });
+}
+
+fn contention(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lock_contention");
+
+    for threads in THREADS {
+        let lock = SpinLock::new(0u64);
+        group.bench_with_input(BenchmarkId::new("SpinLock", threads), &threads, |b, &n| {
+            b.iter(|| contend(n, || *lock.lock() += 1))
+        });
+
+        let lock = Mutex::new(0u64);
+        group.bench_with_input(BenchmarkId::new("Mutex", threads), &threads, |b, &n| {
+            b.iter(|| contend(n, || *lock.lock() += 1))
+        });
+
+        let lock = TicketLock::new(0u64);
+        group.bench_with_input(
+            BenchmarkId::new("TicketLock", threads),
+            &threads,
+            |b, &n| b.iter(|| contend(n, || *lock.lock() += 1)),
+        );
+
+        let lock = McsLock::new(0u64);
+        group.bench_with_input(BenchmarkId::new("McsLock", threads), &threads, |b, &n| {
+            b.iter(|| contend(n, || *lock.lock() += 1))
+        });
+
+        let lock = ClhLock::new(0u64);
+        group.bench_with_input(BenchmarkId::new("ClhLock", threads), &threads, |b, &n| {
+            b.iter(|| contend(n, || *lock.lock() += 1))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, contention);
+criterion_main!(benches);
@@ -0,0 +1,105 @@
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex},
+    thread,
+};
+
+use conutils::unbounded;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+const SENDERS: [usize; 3] = [1, 2, 4];
+const MESSAGES: usize = 10_000;
+
+/// The queue the channel used before the lock-free blocks,
+/// without the sender and receiver counting.
+struct MutexQueue<T> {
+    queue: Mutex<VecDeque<T>>,
+    available: Condvar,
+}
+
+impl<T> MutexQueue<T> {
+    fn new() -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+            available: Condvar::new(),
+        }
+    }
+
+    fn send(&self, item: T) {
+        let was_empty = {
+            let mut queue = self.queue.lock().unwrap();
+            let empty = queue.is_empty();
+            queue.push_back(item);
+            empty
+        };
+        if was_empty {
+            self.available.notify_one();
+        }
+    }
+
+    fn recv(&self) -> T {
+        let mut queue = self.queue.lock().unwrap();
+        loop {
+            match queue.pop_front() {
+                Some(item) => return item,
+                None => queue = self.available.wait(queue).unwrap(),
+            }
+        }
+    }
+}
+
+/// Each of `senders` threads sends `MESSAGES / senders` messages,
+/// which the calling thread receives.
+fn throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mpsc_throughput");
+
+    for senders in SENDERS {
+        let per_sender = MESSAGES / senders;
+
+        group.bench_with_input(
+            BenchmarkId::new("MutexQueue", senders),
+            &senders,
+            |b, &n| {
+                b.iter(|| {
+                    let queue = MutexQueue::new();
+                    thread::scope(|s| {
+                        for _ in 0..n {
+                            s.spawn(|| {
+                                for i in 0..per_sender {
+                                    queue.send(i);
+                                }
+                            });
+                        }
+                        for _ in 0..n * per_sender {
+                            queue.recv();
+                        }
+                    });
+                })
+            },
+        );
+
+        group.bench_with_input(BenchmarkId::new("unbounded", senders), &senders, |b, &n| {
+            b.iter(|| {
+                let (tx, mut rx) = unbounded();
+                thread::scope(|s| {
+                    for _ in 0..n {
+                        let tx = tx.clone();
+                        s.spawn(move || {
+                            for i in 0..per_sender {
+                                tx.send(i).unwrap();
+                            }
+                        });
+                    }
+                    for _ in 0..n * per_sender {
+                        rx.recv().unwrap();
+                    }
+                });
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, throughput);
+criterion_main!(benches);
@@ -0,0 +1,40 @@
+use std::{hint::black_box, thread};
+
+use conutils::{BravoRwLock, RwLock};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const THREADS: usize = 4;
+const READS: usize = 1000;
+
+/// Every thread reads `READS` times, with a single write per round.
+fn read_mostly(read: impl Fn() -> u64 + Sync, write: impl Fn()) {
+    thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..READS {
+                    black_box(read());
+                }
+            });
+        }
+        write();
+    });
+}
+
+fn readers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read_mostly");
+
+    let lock = RwLock::new([0u64; 4]);
+    group.bench_function("RwLock", |b| {
+        b.iter(|| read_mostly(|| lock.read()[0], || lock.write()[0] += 1))
+    });
+
+    let lock = BravoRwLock::new([0u64; 4]);
+    group.bench_function("BravoRwLock", |b| {
+        b.iter(|| read_mostly(|| lock.read()[0], || lock.write()[0] += 1))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, readers);
+criterion_main!(benches);
@@ -1,45 +1,249 @@
 use std::{
+    alloc::{self, Layout},
+    borrow::Borrow,
     cell::UnsafeCell,
-    mem::ManuallyDrop,
+    cmp::Ordering as CmpOrdering,
+    fmt,
+    hash::{Hash, Hasher},
+    mem::{ManuallyDrop, MaybeUninit},
     ops::Deref,
     ptr::NonNull,
     sync::atomic::{fence, AtomicUsize, Ordering},
 };
 
-struct ArcData<T> {
+#[repr(C)]
+struct ArcData<T: ?Sized> {
     /// Number of `Arc`s.
     data_ref_count: AtomicUsize,
     /// Number of `Weak`s, plus one if there are any `Arc`s.
     alloc_ref_count: AtomicUsize,
+    /// Makes sure there's a word right before `data` even without
+    /// padding, see `write_data_offset`.
+    data_offset: usize,
     /// The data. Dropped if there are only weak pointers left.
     data: UnsafeCell<ManuallyDrop<T>>,
 }
 
-pub struct Arc<T> {
+impl<T: ?Sized> ArcData<T> {
+    /// Stores the offset of `data` in the word right before it, which is
+    /// either the `data_offset` field or padding. `from_data_ptr` reads it
+    /// back, since it can't ask a possibly dropped value for its alignment.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to a new allocation, before it's shared.
+    unsafe fn write_data_offset(ptr: NonNull<ArcData<T>>) {
+        let base = ptr.as_ptr() as *mut u8;
+        let data = UnsafeCell::raw_get(std::ptr::addr_of!((*ptr.as_ptr()).data)) as *mut u8;
+        let offset = data.offset_from(base) as usize;
+        data.sub(std::mem::size_of::<usize>())
+            .cast::<usize>()
+            .write(offset);
+    }
+
+    /// Gets the `ArcData` back from a pointer to its `data` field.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must point to the `data` field of an `ArcData<T>`.
+    unsafe fn from_data_ptr(ptr: *const T) -> NonNull<ArcData<T>> {
+        // The data might have been dropped already (e.g. for a `Weak`),
+        // so only look at the offset stored in front of it.
+        let offset = ptr
+            .cast::<u8>()
+            .sub(std::mem::size_of::<usize>())
+            .cast::<usize>()
+            .read();
+        NonNull::new_unchecked(ptr.byte_sub(offset) as *mut ArcData<T>)
+    }
+}
+
+/// The address of a `Weak` created with `Weak::new`.
+const WEAK_SENTINEL: usize = usize::MAX;
+
+pub struct Arc<T: ?Sized> {
     ptr: NonNull<ArcData<T>>,
 }
 
-unsafe impl<T: Send + Sync> Send for Arc<T> {}
-unsafe impl<T: Send + Sync> Sync for Arc<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Send for Arc<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for Arc<T> {}
 
-pub struct Weak<T> {
+pub struct Weak<T: ?Sized> {
     ptr: NonNull<ArcData<T>>,
 }
 
-unsafe impl<T: Send + Sync> Send for Weak<T> {}
-unsafe impl<T: Send + Sync> Sync for Weak<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Send for Weak<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for Weak<T> {}
 
 impl<T> Arc<T> {
     pub fn new(data: T) -> Arc<T> {
+        let ptr = NonNull::from(Box::leak(Box::new(ArcData {
+            data_ref_count: AtomicUsize::new(1),
+            alloc_ref_count: AtomicUsize::new(1),
+            data_offset: 0,
+            data: UnsafeCell::new(ManuallyDrop::new(data)),
+        })));
+        unsafe { ArcData::write_data_offset(ptr) };
+        Arc { ptr }
+    }
+
+    /// Creates an `Arc` whose value can hold a `Weak` pointer to itself.
+    ///
+    /// The `Weak` passed to `f` can be cloned and stored, but upgrading
+    /// it returns `None` until `new_cyclic` returns.
+    pub fn new_cyclic(f: impl FnOnce(&Weak<T>) -> T) -> Arc<T> {
+        // Start without any Arc, just the implicit weak pointer.
+        let ptr = NonNull::from(Box::leak(Box::new(ArcData {
+            data_ref_count: AtomicUsize::new(0),
+            alloc_ref_count: AtomicUsize::new(1),
+            data_offset: 0,
+            data: UnsafeCell::new(ManuallyDrop::new(MaybeUninit::<T>::uninit())),
+        })))
+        .cast::<ArcData<T>>();
+        unsafe { ArcData::write_data_offset(ptr) };
+
+        // If `f` panics, this drops the allocation without touching the data.
+        let weak = Weak { ptr };
+        let data = f(&weak);
+
+        unsafe {
+            // Safety: The data reference counter is zero, so nobody
+            // else is able to access the data yet.
+            std::ptr::write(ptr.as_ref().data.get(), ManuallyDrop::new(data));
+            // Release matches the Acquire in `Weak::upgrade`, so the data
+            // is visible to whoever upgrades a weak pointer.
+            ptr.as_ref().data_ref_count.store(1, Ordering::Release);
+        }
+
+        // The weak pointer becomes the implicit weak pointer of the Arc.
+        std::mem::forget(weak);
+        Arc { ptr }
+    }
+
+    /// Converts an `Arc<T>` into an `Arc<U>` sharing the same allocation,
+    /// where `U` is an unsized view of `T` such as a trait object or a slice.
+    ///
+    /// Stable Rust can't coerce our `Arc` implicitly, so the caller performs
+    /// the unsizing coercion on a reference instead:
+    ///
+    /// ```
+    /// use std::fmt::Display;
+    /// use conutils::{Arc, Mutex};
+    ///
+    /// let a: Arc<dyn Display> = unsafe { Arc::unsize(Arc::new(1), |v| v as &dyn Display) };
+    /// assert_eq!(a.to_string(), "1");
+    ///
+    /// let m: Arc<Mutex<[i32]>> =
+    ///     unsafe { Arc::unsize(Arc::new(Mutex::new([1, 2, 3])), |m| m as _) };
+    /// assert_eq!(m.lock().len(), 3);
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// `f` must only perform an unsizing coercion, so that `U` is an unsized
+    /// view of `T` with the same drop glue. Returning e.g. the inside of a
+    /// `ManuallyDrop<T>`, or a `repr(transparent)` wrapper with its own
+    /// `Drop`, would run the wrong destructor.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `f` returns a reference to anything other than the value
+    /// itself.
+    pub unsafe fn unsize<U: ?Sized>(arc: Self, f: impl FnOnce(&T) -> &U) -> Arc<U> {
+        let data: *const T = &*arc;
+        let unsized_data: *const U = f(&arc);
+        // Only the pointer metadata may change, the pointee must stay the same
+        // value, otherwise dropping through `Arc<U>` would be wrong.
+        assert!(
+            std::ptr::addr_eq(data, unsized_data)
+                && unsafe {
+                    std::mem::size_of_val(&*unsized_data) == std::mem::size_of::<T>()
+                        && std::mem::align_of_val(&*unsized_data) == std::mem::align_of::<T>()
+                },
+            "Arc::unsize must return a reference to the value itself"
+        );
+        std::mem::forget(arc);
         Arc {
-            ptr: NonNull::from(Box::leak(Box::new(ArcData {
-                data_ref_count: AtomicUsize::new(1),
-                alloc_ref_count: AtomicUsize::new(1),
-                data: UnsafeCell::new(ManuallyDrop::new(data)),
-            }))),
+            ptr: unsafe { ArcData::from_data_ptr(unsized_data) },
+        }
+    }
+
+    /// Returns the inner value if this is the only `Arc`,
+    /// otherwise gives the `Arc` back.
+    pub fn try_unwrap(arc: Self) -> Result<T, Self> {
+        // Acquire to match Arc::drop's Release decrement.
+        if arc
+            .data()
+            .data_ref_count
+            .compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(arc);
+        }
+        let arc = ManuallyDrop::new(arc);
+        // Safety: The data reference counter is zero now,
+        // so nothing else will access the data anymore.
+        let data = unsafe { ManuallyDrop::take(&mut *arc.data().data.get()) };
+        drop(Weak { ptr: arc.ptr });
+        Ok(data)
+    }
+
+    /// Drops this `Arc` and returns the inner value if it was the last one.
+    ///
+    /// Unlike `try_unwrap`, when several threads call this on clones of the
+    /// same `Arc`, exactly one of them gets the value.
+    pub fn into_inner(arc: Self) -> Option<T> {
+        let arc = ManuallyDrop::new(arc);
+        if arc.data().data_ref_count.fetch_sub(1, Ordering::Release) != 1 {
+            return None;
         }
+        fence(Ordering::Acquire);
+        // Safety: We just dropped the last Arc, so nothing else
+        // will access the data anymore.
+        let data = unsafe { ManuallyDrop::take(&mut *arc.data().data.get()) };
+        drop(Weak { ptr: arc.ptr });
+        Some(data)
     }
 
+    /// Returns a mutable reference to the data, cloning it first
+    /// if there are other `Arc`s sharing it (copy-on-write).
+    ///
+    /// If only `Weak`s are left besides this `Arc`, the data is moved
+    /// into a new allocation instead and those `Weak`s are disassociated.
+    pub fn make_mut(arc: &mut Self) -> &mut T
+    where
+        T: Clone,
+    {
+        // Acquire to match Arc::drop's Release decrement, and temporarily
+        // take the count to zero so no Weak can be upgraded meanwhile.
+        if arc
+            .data()
+            .data_ref_count
+            .compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // Other Arcs exist, clone the data.
+            *arc = Arc::new((**arc).clone());
+        } else if arc.data().alloc_ref_count.load(Ordering::Relaxed) != 1 {
+            // Only Weaks are left, move the data out and leave them
+            // with an allocation that can never be upgraded again.
+            let weak = Weak { ptr: arc.ptr };
+            // Safety: The data reference counter is zero,
+            // so this is the only access to the data.
+            let data = unsafe { ManuallyDrop::take(&mut *arc.data().data.get()) };
+            unsafe { std::ptr::write(arc, Arc::new(data)) };
+            drop(weak);
+        } else {
+            // We were the only reference, restore the count.
+            arc.data().data_ref_count.store(1, Ordering::Release);
+        }
+        // Safety: Either we just created a fresh Arc, or we've
+        // verified this Arc is the only pointer to the data.
+        unsafe { &mut *arc.data().data.get() }
+    }
+}
+
+impl<T: ?Sized> Arc<T> {
     pub fn get_mut(arc: &mut Self) -> Option<&mut T> {
         if arc
             .data()
@@ -87,12 +291,199 @@ impl<T> Arc<T> {
         }
     }
 
+    /// Gets a raw pointer to the data, without affecting the counts.
+    pub fn as_ptr(this: &Self) -> *const T {
+        this.data().data.get() as *const T
+    }
+
+    /// Consumes the `Arc` and returns a raw pointer to the data.
+    ///
+    /// The strong count is kept, use `Arc::from_raw` to get the `Arc` back.
+    pub fn into_raw(this: Self) -> *const T {
+        let ptr = Self::as_ptr(&this);
+        std::mem::forget(this);
+        ptr
+    }
+
+    /// Constructs an `Arc` back from a pointer returned by `Arc::into_raw`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Arc::into_raw` (or `Arc::as_ptr` with the
+    /// strong count incremented), and every `from_raw` must match one
+    /// strong count owned by the caller.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        Arc {
+            ptr: ArcData::from_data_ptr(ptr),
+        }
+    }
+
+    /// Increments the strong count of the `Arc` behind a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Arc::into_raw` and the `Arc` must still be alive.
+    pub unsafe fn increment_strong_count(ptr: *const T) {
+        let arc = ManuallyDrop::new(Self::from_raw(ptr));
+        let _clone: ManuallyDrop<Arc<T>> = arc.clone();
+    }
+
+    /// Decrements the strong count of the `Arc` behind a raw pointer,
+    /// dropping the data if it was the last one.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Arc::into_raw` and the caller must own
+    /// the strong count being released.
+    pub unsafe fn decrement_strong_count(ptr: *const T) {
+        drop(Self::from_raw(ptr));
+    }
+
+    /// Returns `true` if both `Arc`s point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        std::ptr::addr_eq(this.ptr.as_ptr(), other.ptr.as_ptr())
+    }
+
+    /// Gets the number of `Arc`s pointing to this allocation.
+    pub fn strong_count(this: &Self) -> usize {
+        this.data().data_ref_count.load(Ordering::Acquire)
+    }
+
+    /// Gets the number of `Weak`s pointing to this allocation.
+    pub fn weak_count(this: &Self) -> usize {
+        let n = this.data().alloc_ref_count.load(Ordering::Acquire);
+        // The count is locked by `get_mut`, which can only happen
+        // when there are no `Weak`s.
+        if n == usize::MAX {
+            return 0;
+        }
+        // Don't count the implicit weak pointer of the `Arc`s.
+        n - 1
+    }
+
     fn data(&self) -> &ArcData<T> {
         unsafe { self.ptr.as_ref() }
     }
 }
 
-impl<T> Deref for Arc<T> {
+impl<T> Arc<[T]> {
+    /// The layout of an `ArcData<[T]>` with `len` elements.
+    fn slice_layout(len: usize) -> Layout {
+        // Same layout as the `repr(C)` ArcData: the header, then the slice.
+        let (layout, _) = Layout::new::<ArcData<()>>()
+            .extend(Layout::array::<T>(len).expect("slice too large"))
+            .expect("slice too large");
+        layout.pad_to_align()
+    }
+
+    /// Gets the elements of an `ArcData<[T]>`, without referencing
+    /// them, since they might not be initialized yet.
+    fn slice_elems(ptr: NonNull<ArcData<[T]>>) -> *mut T {
+        unsafe { UnsafeCell::raw_get(std::ptr::addr_of!((*ptr.as_ptr()).data)) as *mut T }
+    }
+
+    /// Allocates an `ArcData<[T]>` with room for `len` elements in the same
+    /// allocation as the counters. The elements are left uninitialized.
+    fn allocate_for_slice(len: usize) -> NonNull<ArcData<[T]>> {
+        let layout = Self::slice_layout(len);
+        unsafe {
+            let mem = alloc::alloc(layout);
+            if mem.is_null() {
+                alloc::handle_alloc_error(layout);
+            }
+            let ptr = std::ptr::slice_from_raw_parts_mut(mem as *mut T, len) as *mut ArcData<[T]>;
+            std::ptr::addr_of_mut!((*ptr).data_ref_count).write(AtomicUsize::new(1));
+            std::ptr::addr_of_mut!((*ptr).alloc_ref_count).write(AtomicUsize::new(1));
+            std::ptr::addr_of_mut!((*ptr).data_offset).write(0);
+            let ptr = NonNull::new_unchecked(ptr);
+            ArcData::write_data_offset(ptr);
+            ptr
+        }
+    }
+
+    /// Creates an `Arc<[T]>` from an iterator yielding exactly `len` elements.
+    fn from_iter_exact(iter: impl Iterator<Item = T>, len: usize) -> Self {
+        /// Drops the elements written so far and frees the allocation,
+        /// if the iterator panics.
+        struct Guard<T> {
+            ptr: NonNull<ArcData<[T]>>,
+            len: usize,
+            written: usize,
+        }
+        impl<T> Drop for Guard<T> {
+            fn drop(&mut self) {
+                unsafe {
+                    std::ptr::drop_in_place(std::ptr::slice_from_raw_parts_mut(
+                        Arc::slice_elems(self.ptr),
+                        self.written,
+                    ));
+                    // Not `Layout::for_value`, the slice isn't fully initialized.
+                    let layout = Arc::<[T]>::slice_layout(self.len);
+                    alloc::dealloc(self.ptr.as_ptr() as *mut u8, layout);
+                }
+            }
+        }
+
+        let mut guard = Guard {
+            ptr: Self::allocate_for_slice(len),
+            len,
+            written: 0,
+        };
+        let elems = Self::slice_elems(guard.ptr);
+        for item in iter.take(len) {
+            unsafe { elems.add(guard.written).write(item) };
+            guard.written += 1;
+        }
+        assert_eq!(guard.written, len, "iterator yielded too few elements");
+
+        let ptr = guard.ptr;
+        std::mem::forget(guard);
+        Arc { ptr }
+    }
+}
+
+impl<T> From<Vec<T>> for Arc<[T]> {
+    fn from(mut v: Vec<T>) -> Self {
+        let ptr = Self::allocate_for_slice(v.len());
+        unsafe {
+            std::ptr::copy_nonoverlapping(v.as_ptr(), Self::slice_elems(ptr), v.len());
+            // The elements have been moved, only free the buffer of the Vec.
+            v.set_len(0);
+        }
+        Arc { ptr }
+    }
+}
+
+impl<T: Clone> From<&[T]> for Arc<[T]> {
+    fn from(v: &[T]) -> Self {
+        Self::from_iter_exact(v.iter().cloned(), v.len())
+    }
+}
+
+impl<T> FromIterator<T> for Arc<[T]> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        // The size hint can't be trusted, so collect the elements first.
+        Self::from(iter.into_iter().collect::<Vec<T>>())
+    }
+}
+
+impl From<&str> for Arc<str> {
+    fn from(s: &str) -> Self {
+        let bytes = Arc::<[u8]>::from(s.as_bytes());
+        // Safety: `str` has the same layout as `[u8]`, and the bytes are valid UTF-8.
+        unsafe { Arc::from_raw(Arc::into_raw(bytes) as *const str) }
+    }
+}
+
+impl From<String> for Arc<str> {
+    fn from(s: String) -> Self {
+        let bytes = Arc::<[u8]>::from(s.into_bytes());
+        // Safety: `str` has the same layout as `[u8]`, and the bytes are valid UTF-8.
+        unsafe { Arc::from_raw(Arc::into_raw(bytes) as *const str) }
+    }
+}
+
+impl<T: ?Sized> Deref for Arc<T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -102,7 +493,7 @@ impl<T> Deref for Arc<T> {
     }
 }
 
-impl<T> Clone for Arc<T> {
+impl<T: ?Sized> Clone for Arc<T> {
     fn clone(&self) -> Self {
         if self.data().data_ref_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
             std::process::abort();
@@ -111,7 +502,7 @@ impl<T> Clone for Arc<T> {
     }
 }
 
-impl<T> Drop for Arc<T> {
+impl<T: ?Sized> Drop for Arc<T> {
     fn drop(&mut self) {
         if self.data().data_ref_count.fetch_sub(1, Ordering::Release) == 1 {
             fence(Ordering::Acquire);
@@ -127,22 +518,119 @@ impl<T> Drop for Arc<T> {
     }
 }
 
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Arc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for Arc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized> fmt::Pointer for Arc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Pointer::fmt(&Arc::as_ptr(self), f)
+    }
+}
+
+impl<T: ?Sized + PartialEq> PartialEq for Arc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for Arc<T> {}
+
+impl<T: ?Sized + PartialOrd> PartialOrd for Arc<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Ord> Ord for Arc<T> {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl<T: ?Sized + Hash> Hash for Arc<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<T: ?Sized> Borrow<T> for Arc<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for Arc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T> From<T> for Arc<T> {
+    fn from(data: T) -> Self {
+        Arc::new(data)
+    }
+}
+
+impl<T: Default> Default for Arc<T> {
+    fn default() -> Self {
+        Arc::new(T::default())
+    }
+}
+
+impl Default for Arc<str> {
+    fn default() -> Self {
+        Arc::from("")
+    }
+}
+
+impl<T> Default for Arc<[T]> {
+    fn default() -> Self {
+        Arc::from(Vec::new())
+    }
+}
+
 impl<T> Weak<T> {
-    fn data(&self) -> &ArcData<T> {
-        unsafe { self.ptr.as_ref() }
+    /// Creates a `Weak` that never upgrades, without allocating anything.
+    pub const fn new() -> Weak<T> {
+        Weak {
+            // Safety: usize::MAX is not null. No allocation can be at that
+            // address, so it's used as a sentinel for "no ArcData".
+            ptr: unsafe { NonNull::new_unchecked(std::ptr::without_provenance_mut(WEAK_SENTINEL)) },
+        }
+    }
+}
+
+impl<T: ?Sized> Weak<T> {
+    /// Returns `None` if this `Weak` was created with `Weak::new`.
+    fn data(&self) -> Option<&ArcData<T>> {
+        if self.ptr.as_ptr().cast::<()>().addr() == WEAK_SENTINEL {
+            return None;
+        }
+        unsafe { Some(self.ptr.as_ref()) }
     }
 
     pub fn upgrade(&self) -> Option<Arc<T>> {
-        let mut n = self.data().data_ref_count.load(Ordering::Relaxed);
+        let data = self.data()?;
+        let mut n = data.data_ref_count.load(Ordering::Relaxed);
         loop {
             if n == 0 {
                 return None;
             }
             assert!(n < usize::MAX);
-            if let Err(e) = self.data().data_ref_count.compare_exchange_weak(
+            // Acquire to see the data written by `Arc::new_cyclic`.
+            if let Err(e) = data.data_ref_count.compare_exchange_weak(
                 n,
                 n + 1,
-                Ordering::Relaxed,
+                Ordering::Acquire,
                 Ordering::Relaxed,
             ) {
                 n = e;
@@ -151,20 +639,104 @@ impl<T> Weak<T> {
             return Some(Arc { ptr: self.ptr });
         }
     }
+
+    /// Gets the number of `Arc`s pointing to this allocation.
+    pub fn strong_count(&self) -> usize {
+        self.data()
+            .map_or(0, |data| data.data_ref_count.load(Ordering::Acquire))
+    }
+
+    /// Gets the number of `Weak`s pointing to this allocation,
+    /// or zero if there are no `Arc`s left.
+    pub fn weak_count(&self) -> usize {
+        let Some(data) = self.data() else {
+            return 0;
+        };
+        let weak = data.alloc_ref_count.load(Ordering::Acquire);
+        let strong = data.data_ref_count.load(Ordering::Acquire);
+        if strong == 0 {
+            0
+        } else {
+            // Don't count the implicit weak pointer of the `Arc`s.
+            weak - 1
+        }
+    }
+
+    /// Gets a raw pointer to the data, without affecting the counts.
+    ///
+    /// The pointer is dangling if there are no `Arc`s left,
+    /// or if this `Weak` was created with `Weak::new`.
+    pub fn as_ptr(&self) -> *const T {
+        if self.data().is_none() {
+            return self.ptr.as_ptr() as *const T;
+        }
+        // Safety: The allocation is still alive, only the data might be
+        // dropped, so don't create a reference to it.
+        unsafe { UnsafeCell::raw_get(std::ptr::addr_of!((*self.ptr.as_ptr()).data)) as *const T }
+    }
+
+    /// Consumes the `Weak` and returns a raw pointer to the data.
+    ///
+    /// The weak count is kept, use `Weak::from_raw` to get the `Weak` back.
+    pub fn into_raw(self) -> *const T {
+        let ptr = self.as_ptr();
+        std::mem::forget(self);
+        ptr
+    }
+
+    /// Constructs a `Weak` back from a pointer returned by `Weak::into_raw`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Weak::into_raw`, and every `from_raw` must
+    /// match one weak count owned by the caller.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        if ptr.cast::<()>().addr() == WEAK_SENTINEL {
+            return Weak {
+                ptr: NonNull::new_unchecked(ptr as *mut ArcData<T>),
+            };
+        }
+        Weak {
+            ptr: ArcData::from_data_ptr(ptr),
+        }
+    }
+
+    /// Returns `true` if both `Weak`s point to the same allocation,
+    /// or were both created with `Weak::new`.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self.ptr.as_ptr(), other.ptr.as_ptr())
+    }
 }
 
-impl<T> Clone for Weak<T> {
+impl<T: ?Sized> fmt::Debug for Weak<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(Weak)")
+    }
+}
+
+impl<T> Default for Weak<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized> Clone for Weak<T> {
     fn clone(&self) -> Self {
-        if self.data().alloc_ref_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
-            std::process::abort();
+        if let Some(data) = self.data() {
+            if data.alloc_ref_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
+                std::process::abort();
+            }
         }
         Weak { ptr: self.ptr }
     }
 }
 
-impl<T> Drop for Weak<T> {
+impl<T: ?Sized> Drop for Weak<T> {
     fn drop(&mut self) {
-        if self.data().alloc_ref_count.fetch_sub(1, Ordering::Release) == 1 {
+        let Some(data) = self.data() else {
+            return;
+        };
+        if data.alloc_ref_count.fetch_sub(1, Ordering::Release) == 1 {
             fence(Ordering::Acquire);
             unsafe {
                 drop(Box::from_raw(self.ptr.as_ptr()));
@@ -218,10 +790,6 @@ mod tests {
 
     // Helper methods for testing reference counts
     impl<T> Arc<T> {
-        fn get_data_ref_count(&self) -> usize {
-            self.data().data_ref_count.load(Ordering::Relaxed)
-        }
-
         fn get_alloc_ref_count(&self) -> usize {
             self.data().alloc_ref_count.load(Ordering::Relaxed)
         }
@@ -232,35 +800,35 @@ mod tests {
         // Test creation and basic reference counting
         let x = Arc::new(42);
         assert_eq!(*x, 42);
-        assert_eq!(x.get_data_ref_count(), 1);
+        assert_eq!(Arc::strong_count(&x), 1);
         assert_eq!(x.get_alloc_ref_count(), 1);
 
         // Test cloning
         let y = Arc::clone(&x);
         assert_eq!(*y, 42);
-        assert_eq!(x.get_data_ref_count(), 2);
+        assert_eq!(Arc::strong_count(&x), 2);
         assert_eq!(x.get_alloc_ref_count(), 1);
 
         // Test dropping
         drop(y);
-        assert_eq!(x.get_data_ref_count(), 1);
+        assert_eq!(Arc::strong_count(&x), 1);
         assert_eq!(x.get_alloc_ref_count(), 1);
     }
 
     #[test]
     fn test_weak_reference() {
         let strong = Arc::new(42);
-        assert_eq!(strong.get_data_ref_count(), 1);
+        assert_eq!(Arc::strong_count(&strong), 1);
         assert_eq!(strong.get_alloc_ref_count(), 1);
 
         let weak = Arc::downgrade(&strong);
-        assert_eq!(strong.get_data_ref_count(), 1); // strong count unchanged
+        assert_eq!(Arc::strong_count(&strong), 1); // strong count unchanged
         assert_eq!(strong.get_alloc_ref_count(), 2); // alloc count increased
 
         // Test upgrade succeeds while strong reference exists
         let upgraded = weak.upgrade().unwrap();
         assert_eq!(*upgraded, 42);
-        assert_eq!(strong.get_data_ref_count(), 2);
+        assert_eq!(Arc::strong_count(&strong), 2);
         assert_eq!(strong.get_alloc_ref_count(), 2);
 
         // Drop all strong references
@@ -281,13 +849,13 @@ mod tests {
         let handle = thread::spawn(move || {
             assert_eq!(*arc2, 42);
             let weak = Arc::downgrade(&arc2);
-            assert_eq!(weak.upgrade().unwrap().get_data_ref_count(), 3);
+            assert_eq!(Arc::strong_count(&weak.upgrade().unwrap()), 3);
         });
 
         assert_eq!(*arc, 42);
         handle.join().unwrap();
 
-        assert_eq!(arc.get_data_ref_count(), 1);
+        assert_eq!(Arc::strong_count(&arc), 1);
         assert_eq!(arc.get_alloc_ref_count(), 1);
     }
 
@@ -312,6 +880,374 @@ mod tests {
         let _ = weak;
     }
 
+    #[test]
+    fn try_unwrap_should_work() {
+        let x = Arc::new(String::from("hello"));
+        let y = x.clone();
+        let x = Arc::try_unwrap(x).unwrap_err();
+        drop(y);
+
+        let weak = x.downgrade();
+        assert_eq!(Arc::try_unwrap(x).unwrap(), "hello");
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn into_inner_should_return_value_exactly_once() {
+        for _ in 0..100 {
+            let x = Arc::new(vec![1, 2, 3]);
+            let y = x.clone();
+            let t = std::thread::spawn(move || Arc::into_inner(y));
+            let a = Arc::into_inner(x);
+            let b = t.join().unwrap();
+            assert!(a.is_some() ^ b.is_some());
+            assert_eq!(a.or(b).unwrap(), [1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn make_mut_should_clone_on_write() {
+        let mut x = Arc::new(1);
+        let y = x.clone();
+        *Arc::make_mut(&mut x) += 1;
+        assert_eq!((*x, *y), (2, 1));
+        assert!(!Arc::ptr_eq(&x, &y));
+
+        // Unique now, so no clone is needed.
+        let ptr = &*x as *const i32;
+        *Arc::make_mut(&mut x) += 1;
+        assert_eq!(*x, 3);
+        assert_eq!(ptr, &*x as *const i32);
+
+        // Outstanding weak pointers get disassociated.
+        let weak = x.downgrade();
+        *Arc::make_mut(&mut x) += 1;
+        assert_eq!(*x, 4);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(Arc::weak_count(&x), 0);
+    }
+
+    #[test]
+    fn counts_and_ptr_eq_should_work() {
+        let x = Arc::new(1);
+        let y = x.clone();
+        let z = Arc::new(1);
+        assert!(Arc::ptr_eq(&x, &y));
+        assert!(!Arc::ptr_eq(&x, &z));
+        assert_eq!(Arc::strong_count(&x), 2);
+        assert_eq!(Arc::weak_count(&x), 0);
+
+        let w1 = x.downgrade();
+        let w2 = w1.clone();
+        assert!(w1.ptr_eq(&w2));
+        assert!(!w1.ptr_eq(&z.downgrade()));
+        assert_eq!(Arc::weak_count(&x), 2);
+        assert_eq!(w1.strong_count(), 2);
+        assert_eq!(w1.weak_count(), 2);
+
+        drop(x);
+        drop(y);
+        assert_eq!(w1.strong_count(), 0);
+        assert_eq!(w1.weak_count(), 0);
+    }
+
+    #[test]
+    fn new_cyclic_should_work() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Node {
+            me: Weak<Node>,
+            child: Option<Arc<Node>>,
+            parent: Weak<Node>,
+        }
+        impl Drop for Node {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        {
+            let root = Arc::new_cyclic(|me| {
+                // Not upgradable while the value is being constructed.
+                assert!(me.upgrade().is_none());
+                let child = Arc::new_cyclic(|child_me| Node {
+                    me: child_me.clone(),
+                    child: None,
+                    parent: me.clone(),
+                });
+                Node {
+                    me: me.clone(),
+                    child: Some(child),
+                    parent: Weak::new(),
+                }
+            });
+            assert!(Arc::ptr_eq(&root.me.upgrade().unwrap(), &root));
+            let child = root.child.as_ref().unwrap();
+            assert!(Arc::ptr_eq(&child.parent.upgrade().unwrap(), &root));
+            assert!(Arc::ptr_eq(&child.me.upgrade().unwrap(), child));
+            assert!(root.parent.upgrade().is_none());
+            assert_eq!(Arc::strong_count(&root), 1);
+            assert_eq!(Arc::weak_count(&root), 2);
+        }
+
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn new_cyclic_panic_should_not_drop_uninit_data() {
+        let _ = Arc::<String>::new_cyclic(|me| {
+            let _me = me.clone();
+            panic!("oops");
+        });
+    }
+
+    #[test]
+    fn weak_new_should_not_upgrade() {
+        let weak = Weak::<String>::new();
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak.strong_count(), 0);
+        assert_eq!(weak.weak_count(), 0);
+
+        let weak2 = weak.clone();
+        assert!(weak.ptr_eq(&weak2));
+        assert!(!weak.ptr_eq(&Arc::new(String::new()).downgrade()));
+        drop(weak);
+        assert!(Weak::<u8>::default().upgrade().is_none());
+    }
+
+    #[test]
+    fn raw_pointer_round_trip_should_work() {
+        let x = Arc::new(String::from("hello"));
+        let ptr = Arc::into_raw(x.clone());
+        assert_eq!(ptr, Arc::as_ptr(&x));
+        assert_eq!(Arc::strong_count(&x), 2);
+
+        // Pass it through a `usize` user-data slot.
+        let user_data = ptr as usize;
+        let t = std::thread::spawn(move || {
+            let ptr = user_data as *const String;
+            unsafe {
+                Arc::increment_strong_count(ptr);
+                assert_eq!(*ptr, "hello");
+                Arc::decrement_strong_count(ptr);
+                Arc::from_raw(ptr)
+            }
+        });
+        let y = t.join().unwrap();
+        assert!(Arc::ptr_eq(&x, &y));
+        drop(y);
+        assert_eq!(Arc::strong_count(&x), 1);
+
+        // Over-aligned data and trait objects go through the same path.
+        #[repr(align(64))]
+        struct Aligned(u8);
+        let a = Arc::new(Aligned(7));
+        let a = unsafe { Arc::from_raw(Arc::into_raw(a)) };
+        assert_eq!(a.0, 7);
+
+        let d: Arc<dyn std::fmt::Display + Send + Sync> = unsafe { Arc::unsize(x, |s| s as _) };
+        let d = unsafe { Arc::from_raw(Arc::into_raw(d)) };
+        assert_eq!(d.to_string(), "hello");
+    }
+
+    #[test]
+    fn weak_raw_pointer_round_trip_should_work() {
+        let x = Arc::new(42u64);
+        let ptr = x.downgrade().into_raw();
+        assert_eq!(ptr, Arc::as_ptr(&x));
+        assert_eq!(Arc::weak_count(&x), 1);
+
+        let weak = unsafe { Weak::from_raw(ptr) };
+        assert_eq!(*weak.upgrade().unwrap(), 42);
+        let ptr = weak.into_raw();
+
+        // The allocation outlives the data while a weak pointer is around.
+        drop(x);
+        let weak = unsafe { Weak::from_raw(ptr) };
+        assert!(weak.upgrade().is_none());
+        drop(weak);
+
+        let empty = unsafe { Weak::from_raw(Weak::<u64>::new().into_raw()) };
+        assert!(empty.upgrade().is_none());
+    }
+
+    #[test]
+    fn unsized_weak_raw_pointer_should_outlive_data() {
+        #[repr(align(64))]
+        struct Aligned(u8);
+        impl fmt::Debug for Aligned {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "Aligned({})", self.0)
+            }
+        }
+
+        let x: Arc<dyn fmt::Debug> = unsafe { Arc::unsize(Arc::new(Aligned(7)), |v| v as _) };
+        let ptr = x.downgrade().into_raw();
+        assert_eq!(ptr as *const u8 as usize % 64, 0);
+        drop(x);
+        // The data is gone, only the allocation is left.
+        let weak = unsafe { Weak::from_raw(ptr) };
+        assert!(weak.upgrade().is_none());
+        assert_eq!(weak.weak_count(), 0);
+
+        let s: Arc<str> = Arc::from("hello");
+        let ptr = s.downgrade().into_raw();
+        drop(s);
+        assert!(unsafe { Weak::from_raw(ptr) }.upgrade().is_none());
+    }
+
+    #[test]
+    fn slice_arc_should_work() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        #[derive(Clone)]
+        struct DetectDrop(u64);
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let v: Vec<DetectDrop> = (0..5).map(DetectDrop).collect();
+        let slice: Arc<[DetectDrop]> = Arc::from(v);
+        // Moving the elements out of the Vec doesn't drop them.
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        assert_eq!(slice.iter().map(|d| d.0).sum::<u64>(), 10);
+
+        let cloned: Arc<[DetectDrop]> = Arc::from(&slice[1..3]);
+        assert_eq!(cloned.len(), 2);
+        assert_eq!(cloned[0].0, 1);
+
+        let weak = slice.downgrade();
+        drop(slice);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 5);
+        assert!(weak.upgrade().is_none());
+        drop(cloned);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 7);
+
+        let collected: Arc<[u8]> = (1..=3).collect();
+        assert_eq!(&*collected, &[1, 2, 3]);
+        let empty: Arc<[String]> = Vec::new().into();
+        assert!(empty.is_empty());
+        let zst: Arc<[()]> = std::iter::repeat_n((), 10).collect();
+        assert_eq!(zst.len(), 10);
+    }
+
+    #[test]
+    fn slice_arc_clone_panic_should_not_leak_or_double_drop() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct PanicOnClone(bool);
+        impl Clone for PanicOnClone {
+            fn clone(&self) -> Self {
+                assert!(!self.0, "clone panicked");
+                PanicOnClone(self.0)
+            }
+        }
+        impl Drop for PanicOnClone {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let v = [PanicOnClone(false), PanicOnClone(false), PanicOnClone(true)];
+        let result = std::panic::catch_unwind(|| Arc::<[PanicOnClone]>::from(&v[..]));
+        assert!(result.is_err());
+        // The two clones made before the panic have been dropped.
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn str_arc_should_work() {
+        let a: Arc<str> = Arc::from("hello");
+        let b: Arc<str> = Arc::from(String::from("world"));
+        assert_eq!(&*a, "hello");
+        assert_eq!(b.len(), 5);
+
+        let b2 = b.clone();
+        let t = std::thread::spawn(move || b2.to_uppercase());
+        assert_eq!(t.join().unwrap(), "WORLD");
+        assert_eq!(Arc::strong_count(&b), 1);
+    }
+
+    #[test]
+    fn std_traits_should_work() {
+        use std::collections::{BTreeSet, HashMap};
+
+        #[derive(Debug, Default, PartialEq, Eq, Hash)]
+        struct Config {
+            name: Arc<str>,
+            values: Arc<[u32]>,
+        }
+
+        let a = Config {
+            name: Arc::from("a"),
+            values: Arc::from(vec![1, 2]),
+        };
+        assert_eq!(format!("{a:?}"), r#"Config { name: "a", values: [1, 2] }"#);
+        assert_eq!(Config::default().name.len(), 0);
+
+        let x: Arc<i32> = 5.into();
+        assert_eq!(x, Arc::new(5));
+        assert!(x < Arc::new(6));
+        assert_eq!(x.to_string(), "5");
+        assert_eq!(format!("{x:p}"), format!("{:p}", Arc::as_ptr(&x)));
+        assert_eq!(format!("{:?}", x.downgrade()), "(Weak)");
+        assert_eq!(*Arc::<u8>::default(), 0);
+
+        // Borrow lets Arc<str> keys be looked up by &str.
+        let mut map = HashMap::new();
+        map.insert(Arc::<str>::from("key"), 1);
+        assert_eq!(map.get("key"), Some(&1));
+        let set: BTreeSet<Arc<i32>> = [3, 1, 2].into_iter().map(Arc::new).collect();
+        assert_eq!(set.iter().map(|x| **x).collect::<Vec<_>>(), [1, 2, 3]);
+        let s: Arc<str> = Arc::from("hi");
+        let s: &str = s.as_ref();
+        assert_eq!(s, "hi");
+    }
+
+    #[test]
+    fn unsize_should_work() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        trait Shape: Send + Sync {
+            fn area(&self) -> u32;
+        }
+        struct Square(u32);
+        impl Shape for Square {
+            fn area(&self) -> u32 {
+                self.0 * self.0
+            }
+        }
+        impl Drop for Square {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let square = Arc::new(Square(3));
+        let weak = square.downgrade();
+        let shape: Arc<dyn Shape> = unsafe { Arc::unsize(square, |s| s as &dyn Shape) };
+        assert_eq!(shape.area(), 9);
+
+        let shape2 = shape.clone();
+        let t = std::thread::spawn(move || shape2.area());
+        assert_eq!(t.join().unwrap(), 9);
+
+        // The weak pointer still refers to the same allocation.
+        assert_eq!(weak.upgrade().unwrap().0, 3);
+        drop(shape);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+        assert!(weak.upgrade().is_none());
+
+        let slice: Arc<[u64]> = unsafe { Arc::unsize(Arc::new([1u64, 2, 3]), |a| a as &[u64]) };
+        assert_eq!(&*slice, &[1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn unsize_to_other_value_should_panic() {
+        static OTHER: [u8; 2] = [0; 2];
+        let _ = unsafe { Arc::unsize(Arc::new([1u8, 2]), |_| &OTHER[..]) };
+    }
+
     #[test]
     fn cycle_reference_no_weak_should_not_free_resource() {
         static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
@@ -0,0 +1,309 @@
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    mem::MaybeUninit,
+    sync::atomic::{fence, AtomicUsize, Ordering},
+};
+
+use crate::{backoff::Backoff, cache_padded::CachePadded};
+
+/// A bounded lock-free MPMC queue, based on Dmitry Vyukov's bounded
+/// MPMC queue.
+///
+/// Positions are stamps made of a lap and an index, where `one_lap` is a
+/// power of two above the capacity. Every slot has a stamp telling whose
+/// turn it is: a producer with stamp `pos` may write the slot once its
+/// stamp is `pos`, and a consumer may read it once it's `pos + 1`. Reading
+/// sets it to `pos + one_lap`, for the producer of the next lap. Stamps
+/// stay consistent when they wrap, whatever the capacity.
+pub struct ArrayQueue<T> {
+    /// Stamp of the next push.
+    tail: CachePadded<AtomicUsize>,
+    /// Stamp of the next pop.
+    head: CachePadded<AtomicUsize>,
+    buffer: Box<[Slot<T>]>,
+    /// The lap unit, its lower bits hold the index.
+    one_lap: usize,
+}
+
+struct Slot<T> {
+    stamp: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send> Send for ArrayQueue<T> {}
+unsafe impl<T: Send> Sync for ArrayQueue<T> {}
+
+impl<T> ArrayQueue<T> {
+    /// Creates a queue that holds at most `capacity` items.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be non-zero");
+        Self {
+            tail: CachePadded::new(AtomicUsize::new(0)),
+            head: CachePadded::new(AtomicUsize::new(0)),
+            buffer: (0..capacity)
+                .map(|i| Slot {
+                    stamp: AtomicUsize::new(i),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            one_lap: (capacity + 1).next_power_of_two(),
+        }
+    }
+
+    /// Returns the stamp after `pos`, moving to the next lap after the last slot.
+    fn next_stamp(&self, pos: usize) -> usize {
+        let index = pos & (self.one_lap - 1);
+        if index + 1 < self.buffer.len() {
+            pos + 1
+        } else {
+            (pos & !(self.one_lap - 1)).wrapping_add(self.one_lap)
+        }
+    }
+
+    /// Pushes `value`, or gives it back if the queue is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let mut backoff = Backoff::new();
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.buffer[pos & (self.one_lap - 1)];
+            // Acquire matches the Release store in `pop`, so the
+            // previous value was moved out before we overwrite it.
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            if stamp == pos {
+                match self.tail.compare_exchange_weak(
+                    pos,
+                    self.next_stamp(pos),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // Safety: Winning the position gives us the slot
+                        // until we bump its stamp.
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.stamp.store(pos + 1, Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(p) => pos = p,
+                }
+            } else if stamp.wrapping_add(self.one_lap) == pos + 1 {
+                // The slot still holds the value from the previous lap.
+                fence(Ordering::SeqCst);
+                if self.head.load(Ordering::Relaxed).wrapping_add(self.one_lap) == pos {
+                    return Err(value);
+                }
+                // A pop is moving it out right now.
+                backoff.snooze();
+                pos = self.tail.load(Ordering::Relaxed);
+            } else {
+                // Another producer took this position, catch up.
+                backoff.snooze();
+                pos = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Pops the oldest value, or returns `None` if the queue is empty.
+    pub fn pop(&self) -> Option<T> {
+        let mut backoff = Backoff::new();
+        let mut pos = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.buffer[pos & (self.one_lap - 1)];
+            // Acquire matches the Release store in `push`.
+            let stamp = slot.stamp.load(Ordering::Acquire);
+            if stamp == pos + 1 {
+                match self.head.compare_exchange_weak(
+                    pos,
+                    self.next_stamp(pos),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.stamp
+                            .store(pos.wrapping_add(self.one_lap), Ordering::Release);
+                        return Some(value);
+                    }
+                    Err(p) => pos = p,
+                }
+            } else if stamp == pos {
+                // Nothing was pushed to this position yet.
+                fence(Ordering::SeqCst);
+                if self.tail.load(Ordering::Relaxed) == pos {
+                    return None;
+                }
+                // A push is writing it right now.
+                backoff.snooze();
+                pos = self.head.load(Ordering::Relaxed);
+            } else {
+                backoff.snooze();
+                pos = self.head.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Returns the number of items in the queue.
+    ///
+    /// Only a snapshot, other threads might push or pop at the same time.
+    pub fn len(&self) -> usize {
+        loop {
+            let tail = self.tail.load(Ordering::SeqCst);
+            let head = self.head.load(Ordering::SeqCst);
+            // Make sure we didn't read the two positions at different times.
+            if self.tail.load(Ordering::SeqCst) == tail {
+                let head_index = head & (self.one_lap - 1);
+                let tail_index = tail & (self.one_lap - 1);
+                return if head_index < tail_index {
+                    tail_index - head_index
+                } else if head_index > tail_index {
+                    self.buffer.len() - head_index + tail_index
+                } else if head == tail {
+                    0
+                } else {
+                    self.buffer.len()
+                };
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.buffer.len()
+    }
+}
+
+impl<T> Drop for ArrayQueue<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+impl<T> fmt::Debug for ArrayQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArrayQueue")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::AtomicUsize, Arc},
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn one_thread_should_work() {
+        let queue = ArrayQueue::new(2);
+        assert!(queue.is_empty());
+        assert_eq!(queue.push(1), Ok(()));
+        assert_eq!(queue.push(2), Ok(()));
+        assert!(queue.is_full());
+        assert_eq!(queue.push(3), Err(3));
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.push(3), Ok(()));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.pop(), None);
+
+        let queue = ArrayQueue::new(1);
+        assert_eq!(queue.push(1), Ok(()));
+        assert_eq!(queue.push(2), Err(2));
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(format!("{queue:?}"), "ArrayQueue { len: 0, capacity: 1 }");
+    }
+
+    #[test]
+    fn stamps_should_wrap_around() {
+        let queue = ArrayQueue::new(3);
+        // Start at the last lap before the stamps wrap.
+        let lap = usize::MAX & !(queue.one_lap - 1);
+        queue.head.store(lap, Ordering::Relaxed);
+        queue.tail.store(lap, Ordering::Relaxed);
+        for (i, slot) in queue.buffer.iter().enumerate() {
+            slot.stamp.store(lap + i, Ordering::Relaxed);
+        }
+
+        for round in 0..3 {
+            for i in 0..3 {
+                assert_eq!(queue.push(round * 3 + i), Ok(()));
+            }
+            assert!(queue.is_full());
+            assert_eq!(queue.push(9), Err(9));
+            for i in 0..3 {
+                assert_eq!(queue.pop(), Some(round * 3 + i));
+            }
+            assert!(queue.is_empty());
+            assert_eq!(queue.pop(), None);
+        }
+    }
+
+    #[test]
+    fn multi_producers_and_consumers_should_work() {
+        const PRODUCERS: usize = 3;
+        const PER_PRODUCER: usize = 5000;
+        let queue = ArrayQueue::new(3);
+        let sum = AtomicUsize::new(0);
+        let popped = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for p in 0..PRODUCERS {
+                let queue = &queue;
+                s.spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        let mut value = p * PER_PRODUCER + i;
+                        while let Err(v) = queue.push(value) {
+                            value = v;
+                            thread::yield_now();
+                        }
+                    }
+                });
+            }
+            for _ in 0..2 {
+                s.spawn(|| {
+                    while popped.load(Ordering::Relaxed) < PRODUCERS * PER_PRODUCER {
+                        match queue.pop() {
+                            Some(v) => {
+                                sum.fetch_add(v, Ordering::Relaxed);
+                                popped.fetch_add(1, Ordering::Relaxed);
+                            }
+                            None => thread::yield_now(),
+                        }
+                    }
+                });
+            }
+        });
+
+        let n = PRODUCERS * PER_PRODUCER;
+        assert_eq!(sum.load(Ordering::Relaxed), n * (n - 1) / 2);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn drop_should_drop_remaining_values() {
+        let value = Arc::new(());
+        let queue = ArrayQueue::new(4);
+        for _ in 0..3 {
+            queue.push(value.clone()).unwrap();
+        }
+        drop(queue.pop());
+        assert_eq!(Arc::strong_count(&value), 3);
+        drop(queue);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
@@ -0,0 +1,631 @@
+use std::{
+    fmt,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ptr,
+    sync::atomic::{AtomicPtr, AtomicUsize, Ordering},
+};
+
+use crate::{Arc, Mutex, Weak};
+
+/// An `Arc<T>` that can be loaded and replaced atomically.
+///
+/// Readers never take a lock. The race between loading the pointer and
+/// incrementing its strong count is solved with two generations of reader
+/// counters: a writer replaces the pointer first, then waits until every
+/// reader that could have seen the old pointer has taken its strong count,
+/// before the old `Arc` is handed back (and possibly dropped).
+pub struct AtomicArc<T> {
+    slot: Slot<T, Arc<T>>,
+}
+
+/// An `Option<Arc<T>>` that can be loaded and replaced atomically.
+///
+/// Works the same way as `AtomicArc`, with `None` stored as a null pointer.
+pub struct AtomicOptionArc<T> {
+    slot: Slot<T, Option<Arc<T>>>,
+}
+
+/// A `Weak<T>` that can be loaded and replaced atomically.
+///
+/// Works the same way as `AtomicArc`, but the slot owns a weak count
+/// instead, so it doesn't keep the value alive.
+pub struct AtomicWeak<T> {
+    slot: Slot<T, Weak<T>>,
+}
+
+/// The error returned by a failed `compare_exchange`.
+#[derive(Debug)]
+pub struct CompareExchangeError<P> {
+    /// The value that was in the slot.
+    pub current: P,
+    /// The value that was passed in to be stored.
+    pub new: P,
+}
+
+impl<T> AtomicArc<T> {
+    pub fn new(value: Arc<T>) -> Self {
+        Self {
+            slot: Slot::new(value),
+        }
+    }
+
+    /// Gets a new `Arc` to the current value.
+    pub fn load(&self) -> Arc<T> {
+        self.slot.load()
+    }
+
+    /// Replaces the current value, dropping the old one.
+    pub fn store(&self, value: Arc<T>) {
+        drop(self.swap(value));
+    }
+
+    /// Replaces the current value, returning the old one.
+    pub fn swap(&self, value: Arc<T>) -> Arc<T> {
+        self.slot.swap(value)
+    }
+
+    /// Replaces the current value with `new` if it's the same
+    /// allocation as `current` (see `Arc::ptr_eq`).
+    ///
+    /// Returns the previous value either way, so the swap succeeded
+    /// if it's `ptr_eq` to `current`. On failure, `new` is dropped.
+    pub fn compare_and_swap(&self, current: &Arc<T>, new: Arc<T>) -> Arc<T> {
+        match self.slot.compare_exchange(Arc::as_ptr(current), new) {
+            Ok(previous) => previous,
+            Err(e) => e.current,
+        }
+    }
+
+    pub fn into_inner(self) -> Arc<T> {
+        self.slot.into_inner()
+    }
+}
+
+impl<T> AtomicOptionArc<T> {
+    pub fn new(value: Option<Arc<T>>) -> Self {
+        Self {
+            slot: Slot::new(value),
+        }
+    }
+
+    /// Creates an empty slot.
+    pub fn empty() -> Self {
+        Self::new(None)
+    }
+
+    /// Gets a new `Arc` to the current value, if any.
+    pub fn load(&self) -> Option<Arc<T>> {
+        self.slot.load()
+    }
+
+    /// Replaces the current value, dropping the old one.
+    pub fn set(&self, value: Option<Arc<T>>) {
+        drop(self.swap(value));
+    }
+
+    /// Replaces the current value, returning the old one.
+    pub fn swap(&self, value: Option<Arc<T>>) -> Option<Arc<T>> {
+        self.slot.swap(value)
+    }
+
+    /// Takes the current value out, leaving the slot empty.
+    pub fn take(&self) -> Option<Arc<T>> {
+        self.swap(None)
+    }
+
+    /// Replaces the current value with `new` if it's the same
+    /// allocation as `current`, or both are `None`.
+    ///
+    /// Returns the previous value on success.
+    pub fn compare_exchange(
+        &self,
+        current: Option<&Arc<T>>,
+        new: Option<Arc<T>>,
+    ) -> Result<Option<Arc<T>>, CompareExchangeError<Option<Arc<T>>>> {
+        let current = current.map_or(ptr::null(), Arc::as_ptr);
+        self.slot.compare_exchange(current, new)
+    }
+
+    pub fn into_inner(self) -> Option<Arc<T>> {
+        self.slot.into_inner()
+    }
+}
+
+impl<T> AtomicWeak<T> {
+    pub fn new(value: Weak<T>) -> Self {
+        Self {
+            slot: Slot::new(value),
+        }
+    }
+
+    /// Creates a slot holding `Weak::new()`, which never upgrades.
+    pub fn empty() -> Self {
+        Self::new(Weak::new())
+    }
+
+    /// Gets a new `Weak` to the current value.
+    pub fn load(&self) -> Weak<T> {
+        self.slot.load()
+    }
+
+    /// Gets an `Arc` to the current value, if it's still alive.
+    pub fn upgrade(&self) -> Option<Arc<T>> {
+        self.load().upgrade()
+    }
+
+    /// Replaces the current value, dropping the old one.
+    pub fn set(&self, value: Weak<T>) {
+        drop(self.swap(value));
+    }
+
+    /// Replaces the current value, returning the old one.
+    pub fn swap(&self, value: Weak<T>) -> Weak<T> {
+        self.slot.swap(value)
+    }
+
+    /// Takes the current value out, leaving `Weak::new()` behind.
+    pub fn take(&self) -> Weak<T> {
+        self.swap(Weak::new())
+    }
+
+    /// Replaces the current value with `new` if it points to the same
+    /// allocation as `current` (see `Weak::ptr_eq`).
+    ///
+    /// Returns the previous value on success.
+    pub fn compare_exchange(
+        &self,
+        current: &Weak<T>,
+        new: Weak<T>,
+    ) -> Result<Weak<T>, CompareExchangeError<Weak<T>>> {
+        self.slot.compare_exchange(current.as_ptr(), new)
+    }
+
+    pub fn into_inner(self) -> Weak<T> {
+        self.slot.into_inner()
+    }
+}
+
+/// A smart pointer that can be stored in a `Slot` as a raw pointer,
+/// owning one count of whatever kind the pointer holds.
+trait SlotPtr<T>: Clone {
+    fn into_raw(self) -> *mut T;
+
+    /// # Safety
+    ///
+    /// `ptr` must come from `into_raw`, and its count is taken over.
+    unsafe fn from_raw(ptr: *mut T) -> Self;
+
+    /// Creates a new pointer, incrementing the count.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `into_raw`, and its count must still be owned
+    /// by someone.
+    unsafe fn clone_raw(ptr: *mut T) -> Self {
+        let p = ManuallyDrop::new(Self::from_raw(ptr));
+        (*p).clone()
+    }
+}
+
+impl<T> SlotPtr<T> for Arc<T> {
+    fn into_raw(self) -> *mut T {
+        Arc::into_raw(self) as *mut T
+    }
+
+    unsafe fn from_raw(ptr: *mut T) -> Self {
+        Arc::from_raw(ptr)
+    }
+}
+
+impl<T> SlotPtr<T> for Option<Arc<T>> {
+    fn into_raw(self) -> *mut T {
+        self.map_or(ptr::null_mut(), |arc| Arc::into_raw(arc) as *mut T)
+    }
+
+    unsafe fn from_raw(ptr: *mut T) -> Self {
+        (!ptr.is_null()).then(|| Arc::from_raw(ptr))
+    }
+}
+
+impl<T> SlotPtr<T> for Weak<T> {
+    fn into_raw(self) -> *mut T {
+        Weak::into_raw(self) as *mut T
+    }
+
+    unsafe fn from_raw(ptr: *mut T) -> Self {
+        Weak::from_raw(ptr)
+    }
+}
+
+/// The lock-free slot behind `AtomicArc`, `AtomicOptionArc` and `AtomicWeak`.
+struct Slot<T, P: SlotPtr<T>> {
+    /// Pointer from `P::into_raw`, owning one count.
+    ptr: AtomicPtr<T>,
+    /// The number of readers between loading `ptr` and incrementing the
+    /// count, for each generation.
+    readers: [AtomicUsize; 2],
+    /// New readers register in `readers[generation % 2]`.
+    generation: AtomicUsize,
+    /// Serializes writers, so only one of them flips generations at a time.
+    writer: Mutex<()>,
+    _marker: PhantomData<P>,
+}
+
+impl<T, P: SlotPtr<T>> Slot<T, P> {
+    fn new(value: P) -> Self {
+        Self {
+            ptr: AtomicPtr::new(value.into_raw()),
+            readers: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            generation: AtomicUsize::new(0),
+            writer: Mutex::new(()),
+            _marker: PhantomData,
+        }
+    }
+
+    fn load(&self) -> P {
+        let generation = self.generation.load(Ordering::SeqCst) % 2;
+        self.readers[generation].fetch_add(1, Ordering::SeqCst);
+        let ptr = self.ptr.load(Ordering::SeqCst);
+        // Safety: The pointer can't be released while we're registered as
+        // a reader, writers wait for us before giving up its count.
+        let p = unsafe { P::clone_raw(ptr) };
+        // Release matches the Acquire in `wait_for_readers`.
+        self.readers[generation].fetch_sub(1, Ordering::Release);
+        p
+    }
+
+    fn swap(&self, value: P) -> P {
+        let _writer = self.writer.lock();
+        let old = self.ptr.swap(value.into_raw(), Ordering::SeqCst);
+        self.wait_for_readers();
+        // Safety: The count owned by `ptr` is now ours.
+        unsafe { P::from_raw(old) }
+    }
+
+    fn compare_exchange(&self, current: *const T, new: P) -> Result<P, CompareExchangeError<P>> {
+        let _writer = self.writer.lock();
+        let ptr = self.ptr.load(Ordering::SeqCst);
+        if !ptr::eq(ptr, current) {
+            return Err(CompareExchangeError {
+                // Safety: Nobody can replace the pointer while we're
+                // holding the writer lock, so it's still alive.
+                current: unsafe { P::clone_raw(ptr) },
+                new,
+            });
+        }
+        self.ptr.store(new.into_raw(), Ordering::SeqCst);
+        self.wait_for_readers();
+        // Safety: The count owned by `ptr` is now ours.
+        Ok(unsafe { P::from_raw(ptr) })
+    }
+
+    fn into_inner(self) -> P {
+        let ptr = self.ptr.load(Ordering::Relaxed);
+        // Nothing else in the slot needs to be dropped.
+        std::mem::forget(self);
+        // Safety: The count owned by `ptr` is now ours.
+        unsafe { P::from_raw(ptr) }
+    }
+
+    /// Waits until every reader that might have loaded the old pointer
+    /// has incremented its count.
+    ///
+    /// Must be called with the writer lock held, after replacing `ptr`.
+    fn wait_for_readers(&self) {
+        // A reader that registers after we observe its counter at zero will
+        // load the new pointer. Flipping the generation first keeps new
+        // readers away from the counter we're waiting on, so it will drain.
+        for _ in 0..2 {
+            let generation = self.generation.fetch_add(1, Ordering::SeqCst) % 2;
+            let mut spin_count = 0;
+            while self.readers[generation].load(Ordering::Acquire) != 0 {
+                if spin_count < 100 {
+                    spin_count += 1;
+                    std::hint::spin_loop();
+                } else {
+                    std::thread::yield_now();
+                }
+            }
+        }
+    }
+}
+
+impl<T, P: SlotPtr<T>> Drop for Slot<T, P> {
+    fn drop(&mut self) {
+        // Safety: The count owned by `ptr` is ours, and nobody
+        // else can be loading it anymore.
+        drop(unsafe { P::from_raw(*self.ptr.get_mut()) });
+    }
+}
+
+impl<T> From<Arc<T>> for AtomicArc<T> {
+    fn from(value: Arc<T>) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Default> Default for AtomicArc<T> {
+    fn default() -> Self {
+        Self::new(Arc::default())
+    }
+}
+
+impl<T> From<Option<Arc<T>>> for AtomicOptionArc<T> {
+    fn from(value: Option<Arc<T>>) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T> Default for AtomicOptionArc<T> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<T> From<Weak<T>> for AtomicWeak<T> {
+    fn from(value: Weak<T>) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T> Default for AtomicWeak<T> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for AtomicArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AtomicArc").field(&self.load()).finish()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for AtomicOptionArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AtomicOptionArc")
+            .field(&self.load())
+            .finish()
+    }
+}
+
+impl<T> fmt::Debug for AtomicWeak<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AtomicWeak").field(&self.load()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicBool, thread};
+
+    use super::*;
+
+    #[test]
+    fn one_thread_should_work() {
+        let a = AtomicArc::new(Arc::new(1));
+        assert_eq!(*a.load(), 1);
+
+        let old = a.swap(Arc::new(2));
+        assert_eq!(*old, 1);
+        assert_eq!(Arc::strong_count(&old), 1);
+
+        let current = a.load();
+        assert_eq!(Arc::strong_count(&current), 2);
+        a.store(Arc::new(3));
+        assert_eq!(*current, 2);
+        assert_eq!(Arc::strong_count(&current), 1);
+        assert_eq!(format!("{a:?}"), "AtomicArc(3)");
+        assert_eq!(*a.into_inner(), 3);
+    }
+
+    #[test]
+    fn compare_and_swap_should_work() {
+        let first = Arc::new(1);
+        let a = AtomicArc::new(first.clone());
+
+        let prev = a.compare_and_swap(&Arc::new(1), Arc::new(2));
+        assert!(Arc::ptr_eq(&prev, &first));
+        assert_eq!(*a.load(), 1);
+        drop(prev);
+
+        let prev = a.compare_and_swap(&first, Arc::new(2));
+        assert!(Arc::ptr_eq(&prev, &first));
+        assert_eq!(*a.load(), 2);
+        drop(prev);
+        assert_eq!(Arc::strong_count(&first), 1);
+    }
+
+    #[test]
+    fn concurrent_swaps_and_loads_should_work() {
+        static CREATED: AtomicUsize = AtomicUsize::new(0);
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        /// A value whose fields would disagree if it was used after free.
+        struct Config {
+            version: usize,
+            double: usize,
+        }
+        impl Config {
+            fn new(version: usize) -> Arc<Config> {
+                CREATED.fetch_add(1, Ordering::Relaxed);
+                Arc::new(Config {
+                    version,
+                    double: version * 2,
+                })
+            }
+        }
+        impl Drop for Config {
+            fn drop(&mut self) {
+                assert_eq!(self.version * 2, self.double);
+                self.double = usize::MAX;
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let config = AtomicArc::new(Config::new(0));
+        let done = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut last = 0;
+                    while !done.load(Ordering::Relaxed) {
+                        let c = config.load();
+                        assert_eq!(c.version * 2, c.double);
+                        last = last.max(c.version);
+                    }
+                    last
+                });
+            }
+
+            let writers: Vec<_> = (0..4)
+                .map(|w| {
+                    let config = &config;
+                    s.spawn(move || {
+                        for i in 1..=2000 {
+                            let version = w * 10000 + i;
+                            if i % 2 == 0 {
+                                config.store(Config::new(version));
+                            } else {
+                                let current = config.load();
+                                config.compare_and_swap(&current, Config::new(version));
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for w in writers {
+                w.join().unwrap();
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+
+        // Everything except the current value has been freed.
+        assert_eq!(
+            CREATED.load(Ordering::Relaxed) - 1,
+            DROPPED.load(Ordering::Relaxed)
+        );
+        drop(config);
+        assert_eq!(
+            CREATED.load(Ordering::Relaxed),
+            DROPPED.load(Ordering::Relaxed)
+        );
+    }
+
+    #[test]
+    fn option_arc_should_work() {
+        let a = AtomicOptionArc::empty();
+        assert!(a.load().is_none());
+        assert!(a.take().is_none());
+
+        let one = Arc::new(1);
+        a.set(Some(one.clone()));
+        assert_eq!(Arc::strong_count(&one), 2);
+        assert_eq!(a.load().as_deref(), Some(&1));
+
+        // Wrong expectation: nothing changes and `new` is handed back.
+        let e = a.compare_exchange(None, Some(Arc::new(2))).unwrap_err();
+        assert!(Arc::ptr_eq(e.current.as_ref().unwrap(), &one));
+        assert_eq!(e.new.as_deref(), Some(&2));
+        drop(e);
+
+        let prev = a.compare_exchange(Some(&one), None).unwrap();
+        assert!(Arc::ptr_eq(&prev.unwrap(), &one));
+        assert!(a.load().is_none());
+        a.compare_exchange(None, Some(Arc::new(3))).unwrap();
+        assert_eq!(format!("{a:?}"), "AtomicOptionArc(Some(3))");
+
+        assert_eq!(*a.take().unwrap(), 3);
+        assert!(a.into_inner().is_none());
+        assert_eq!(Arc::strong_count(&one), 1);
+    }
+
+    #[test]
+    fn atomic_weak_should_work() {
+        let parent = Arc::new(String::from("parent"));
+        let w = AtomicWeak::empty();
+        assert!(w.upgrade().is_none());
+
+        w.set(parent.downgrade());
+        assert_eq!(Arc::weak_count(&parent), 1);
+        assert_eq!(*w.upgrade().unwrap(), "parent");
+
+        let other = Arc::new(String::from("other"));
+        let e = w
+            .compare_exchange(&Weak::new(), other.downgrade())
+            .unwrap_err();
+        assert!(e.current.ptr_eq(&parent.downgrade()));
+        drop(e);
+
+        let prev = w
+            .compare_exchange(&parent.downgrade(), other.downgrade())
+            .unwrap();
+        assert!(prev.ptr_eq(&parent.downgrade()));
+        drop(prev);
+        assert_eq!(Arc::weak_count(&parent), 0);
+
+        // The slot doesn't keep the value alive.
+        drop(other);
+        assert!(w.upgrade().is_none());
+        assert!(w.take().upgrade().is_none());
+        assert!(w.load().ptr_eq(&Weak::new()));
+    }
+
+    #[test]
+    fn concurrent_parent_back_pointers_should_work() {
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+        struct Node {
+            id: usize,
+            parent: AtomicWeak<Node>,
+            child: AtomicOptionArc<Node>,
+        }
+        impl Drop for Node {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let node = |id| {
+            Arc::new(Node {
+                id,
+                parent: AtomicWeak::empty(),
+                child: AtomicOptionArc::empty(),
+            })
+        };
+
+        let root = node(0);
+        thread::scope(|s| {
+            // Keep replacing the child of the root.
+            s.spawn(|| {
+                for id in 1..=1000 {
+                    let child = node(id);
+                    child.parent.set(root.downgrade());
+                    root.child.set(Some(child));
+                }
+            });
+            // Detach whatever child is there, clearing its back pointer.
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    if let Some(child) = root.child.take() {
+                        let parent = child.parent.take().upgrade();
+                        assert_eq!(parent.unwrap().id, 0);
+                        assert!(child.parent.upgrade().is_none());
+                    }
+                }
+            });
+            // Follow the links concurrently.
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    if let Some(child) = root.child.load() {
+                        if let Some(parent) = child.parent.upgrade() {
+                            assert!(Arc::ptr_eq(&parent, &root));
+                        }
+                    }
+                }
+            });
+        });
+
+        drop(root);
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 1001);
+    }
+}
@@ -0,0 +1,26 @@
+/// Exponential backoff for spin loops.
+///
+/// Spins for a while, then starts yielding to the OS so a preempted lock
+/// holder gets a chance to run.
+pub(crate) struct Backoff {
+    step: u32,
+}
+
+const SPIN_LIMIT: u32 = 6;
+
+impl Backoff {
+    pub(crate) const fn new() -> Self {
+        Self { step: 0 }
+    }
+
+    pub(crate) fn snooze(&mut self) {
+        if self.step <= SPIN_LIMIT {
+            for _ in 0..1 << self.step {
+                std::hint::spin_loop();
+            }
+            self.step += 1;
+        } else {
+            std::thread::yield_now();
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+use atomic_wait::{wait, wake_all};
+
+use crate::{futex, Mutex};
+
+/// Blocks threads until `n` of them are waiting, then releases them all.
+///
+/// The barrier can be reused right away for the next round.
+pub struct Barrier {
+    n: usize,
+    /// Number of threads waiting in the current generation.
+    count: Mutex<usize>,
+    /// Incremented (with `count` locked) every time the threads are released.
+    generation: AtomicU32,
+}
+
+/// A `Barrier` that runs an action on the leader before releasing the others.
+pub struct CyclicBarrier<F> {
+    barrier: Barrier,
+    action: F,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult {
+    /// Exactly one thread of every generation is the leader.
+    pub is_leader: bool,
+}
+
+impl Barrier {
+    pub const fn new(n: usize) -> Self {
+        Self {
+            n,
+            count: Mutex::new(0),
+            generation: AtomicU32::new(0),
+        }
+    }
+
+    pub fn wait(&self) -> BarrierWaitResult {
+        self.wait_until(None, || {}).unwrap()
+    }
+
+    /// Waits for the other threads, giving up after `timeout`.
+    ///
+    /// On timeout this thread no longer counts as waiting,
+    /// so the others still need `n` threads to be released.
+    /// A timeout too large to represent waits forever.
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<BarrierWaitResult> {
+        self.wait_until(Instant::now().checked_add(timeout), || {})
+    }
+
+    fn wait_until(
+        &self,
+        deadline: Option<Instant>,
+        action: impl FnOnce(),
+    ) -> Option<BarrierWaitResult> {
+        let mut count = self.count.lock();
+        let generation = self.generation.load(Ordering::Relaxed);
+        *count += 1;
+        if *count >= self.n {
+            *count = 0;
+            // Release the others even if the action panics.
+            let _release = Release(self);
+            action();
+            return Some(BarrierWaitResult { is_leader: true });
+        }
+        drop(count);
+
+        loop {
+            // Acquire matches the Release increment of the leader.
+            if self.generation.load(Ordering::Acquire) != generation {
+                return Some(BarrierWaitResult { is_leader: false });
+            }
+            match deadline {
+                None => wait(&self.generation, generation),
+                Some(deadline) => {
+                    if !futex::wait_until(&self.generation, generation, deadline) {
+                        let mut count = self.count.lock();
+                        // The generation only changes with `count` locked,
+                        // so we either got released or can still leave.
+                        if self.generation.load(Ordering::Acquire) != generation {
+                            return Some(BarrierWaitResult { is_leader: false });
+                        }
+                        *count -= 1;
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Starts the next generation and wakes all waiters when dropped.
+///
+/// Must be created while `count` is locked, and dropped before unlocking.
+struct Release<'a>(&'a Barrier);
+
+impl Drop for Release<'_> {
+    fn drop(&mut self) {
+        self.0.generation.fetch_add(1, Ordering::Release);
+        wake_all(&self.0.generation);
+    }
+}
+
+impl<F: Fn()> CyclicBarrier<F> {
+    pub const fn new(n: usize, action: F) -> Self {
+        Self {
+            barrier: Barrier::new(n),
+            action,
+        }
+    }
+
+    /// Waits for the other threads. The leader runs the action
+    /// before anyone returns.
+    pub fn wait(&self) -> BarrierWaitResult {
+        self.barrier.wait_until(None, &self.action).unwrap()
+    }
+
+    /// Like `wait`, but gives up after `timeout`.
+    pub fn wait_timeout(&self, timeout: Duration) -> Option<BarrierWaitResult> {
+        self.barrier
+            .wait_until(Instant::now().checked_add(timeout), &self.action)
+    }
+}
+
+impl fmt::Debug for Barrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Barrier")
+            .field("n", &self.n)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F> fmt::Debug for CyclicBarrier<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CyclicBarrier")
+            .field("n", &self.barrier.n)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicUsize, thread};
+
+    use super::*;
+
+    #[test]
+    fn barrier_should_work() {
+        const THREADS: usize = 4;
+        const ROUNDS: usize = 50;
+        let barrier = Barrier::new(THREADS);
+        let arrived = AtomicUsize::new(0);
+        let leaders = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    for round in 0..ROUNDS {
+                        arrived.fetch_add(1, Ordering::Relaxed);
+                        if barrier.wait().is_leader {
+                            leaders.fetch_add(1, Ordering::Relaxed);
+                        }
+                        // Everyone of this round arrived before anyone left.
+                        assert!(arrived.load(Ordering::Relaxed) >= (round + 1) * THREADS);
+                        barrier.wait();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(leaders.load(Ordering::Relaxed), ROUNDS);
+    }
+
+    #[test]
+    fn wait_timeout_should_leave_the_barrier() {
+        let barrier = Barrier::new(2);
+        assert_eq!(barrier.wait_timeout(Duration::from_millis(10)), None);
+
+        // The timed out thread doesn't count, two more are needed.
+        let results = thread::scope(|s| {
+            let t = s.spawn(|| barrier.wait_timeout(Duration::from_secs(10)).unwrap());
+            let r = barrier.wait();
+            [r.is_leader, t.join().unwrap().is_leader]
+        });
+        assert_eq!(results.iter().filter(|&&l| l).count(), 1);
+    }
+
+    #[test]
+    fn huge_timeout_should_wait_forever() {
+        let barrier = Barrier::new(2);
+        let cyclic = CyclicBarrier::new(2, || {});
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                barrier.wait();
+                cyclic.wait();
+            });
+            assert!(barrier.wait_timeout(Duration::MAX).is_some());
+            assert!(cyclic.wait_timeout(Duration::MAX).is_some());
+        });
+    }
+
+    #[test]
+    fn cyclic_barrier_should_run_action_before_release() {
+        const THREADS: usize = 3;
+        let actions = AtomicUsize::new(0);
+        let barrier = CyclicBarrier::new(THREADS, || {
+            actions.fetch_add(1, Ordering::Relaxed);
+        });
+
+        thread::scope(|s| {
+            for _ in 0..THREADS {
+                s.spawn(|| {
+                    for round in 1..=10 {
+                        barrier.wait();
+                        assert_eq!(actions.load(Ordering::Relaxed), round);
+                        // Don't let the leader of the next round run ahead.
+                        barrier.barrier.wait();
+                    }
+                });
+            }
+        });
+    }
+}
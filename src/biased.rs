@@ -0,0 +1,277 @@
+use std::{
+    cell::Cell,
+    fmt,
+    marker::PhantomData,
+    ops::Deref,
+    ptr::NonNull,
+    sync::atomic::{fence, AtomicUsize, Ordering},
+    thread::{self, ThreadId},
+};
+
+struct BiasedData<T> {
+    /// The thread that may use `biased_count`.
+    owner: ThreadId,
+    /// Number of `BiasedRc`s. Only ever touched by the owner thread.
+    biased_count: Cell<usize>,
+    /// Number of `BiasedArc`s, plus one if there are any `BiasedRc`s.
+    shared_count: AtomicUsize,
+    data: T,
+}
+
+/// A biased reference counted pointer for the owner thread.
+///
+/// Cloning and dropping a `BiasedRc` only updates a non-atomic count, so it
+/// costs as much as an `Rc`. It can't be sent to other threads, use
+/// `BiasedRc::to_shared` to get a `BiasedArc` for them instead, which
+/// updates an atomic count like `Arc`.
+///
+/// All `BiasedRc`s together hold a single count of the atomic counter, so
+/// the owner thread only does atomic operations when the first one is
+/// created and the last one is dropped.
+pub struct BiasedRc<T> {
+    ptr: NonNull<BiasedData<T>>,
+    _no_send: PhantomData<*const ()>,
+}
+
+/// The shared counterpart of `BiasedRc`, usable from any thread.
+pub struct BiasedArc<T> {
+    ptr: NonNull<BiasedData<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for BiasedArc<T> {}
+unsafe impl<T: Send + Sync> Sync for BiasedArc<T> {}
+
+thread_local! {
+    /// Cached, since `thread::current()` clones an `Arc`.
+    static THREAD_ID: ThreadId = thread::current().id();
+}
+
+fn current_thread_id() -> ThreadId {
+    THREAD_ID.with(|id| *id)
+}
+
+impl<T> BiasedRc<T> {
+    /// Creates a new value, owned by the current thread.
+    pub fn new(data: T) -> Self {
+        BiasedRc {
+            ptr: NonNull::from(Box::leak(Box::new(BiasedData {
+                owner: current_thread_id(),
+                biased_count: Cell::new(1),
+                shared_count: AtomicUsize::new(1),
+                data,
+            }))),
+            _no_send: PhantomData,
+        }
+    }
+
+    /// Gets a pointer that can be sent to other threads.
+    pub fn to_shared(this: &Self) -> BiasedArc<T> {
+        if this.data().shared_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+        BiasedArc { ptr: this.ptr }
+    }
+
+    /// Gets the number of `BiasedRc`s pointing to this allocation.
+    pub fn biased_count(this: &Self) -> usize {
+        this.data().biased_count.get()
+    }
+
+    /// Returns `true` if both pointers point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    fn data(&self) -> &BiasedData<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> BiasedArc<T> {
+    /// Creates a new value, owned by the current thread.
+    pub fn new(data: T) -> Self {
+        let local = BiasedRc::new(data);
+        BiasedRc::to_shared(&local)
+    }
+
+    /// Gets a `BiasedRc` if the current thread is the owner thread.
+    pub fn to_local(this: &Self) -> Option<BiasedRc<T>> {
+        let data = this.data();
+        if data.owner != current_thread_id() {
+            return None;
+        }
+        // Safety: We're on the owner thread, so nobody else touches it.
+        let n = data.biased_count.get();
+        if n == 0 {
+            // The first `BiasedRc` takes a count of the shared counter,
+            // which can't be zero since we are a `BiasedArc`.
+            data.shared_count.fetch_add(1, Ordering::Relaxed);
+        }
+        data.biased_count.set(n + 1);
+        Some(BiasedRc {
+            ptr: this.ptr,
+            _no_send: PhantomData,
+        })
+    }
+
+    /// Returns `true` if both pointers point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    fn data(&self) -> &BiasedData<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+/// Releases one count of the shared counter, dropping the data if it was the last.
+///
+/// # Safety
+///
+/// The caller must own one count of the shared counter.
+unsafe fn drop_shared<T>(ptr: NonNull<BiasedData<T>>) {
+    if ptr.as_ref().shared_count.fetch_sub(1, Ordering::Release) == 1 {
+        fence(Ordering::Acquire);
+        drop(Box::from_raw(ptr.as_ptr()));
+    }
+}
+
+impl<T> Deref for BiasedRc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data().data
+    }
+}
+
+impl<T> Deref for BiasedArc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data().data
+    }
+}
+
+impl<T> Clone for BiasedRc<T> {
+    fn clone(&self) -> Self {
+        let count = &self.data().biased_count;
+        count.set(count.get() + 1);
+        BiasedRc {
+            ptr: self.ptr,
+            _no_send: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for BiasedArc<T> {
+    fn clone(&self) -> Self {
+        if self.data().shared_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+        BiasedArc { ptr: self.ptr }
+    }
+}
+
+impl<T> Drop for BiasedRc<T> {
+    fn drop(&mut self) {
+        let count = &self.data().biased_count;
+        let n = count.get() - 1;
+        count.set(n);
+        if n == 0 {
+            // The last `BiasedRc` gives up the count of the shared counter.
+            unsafe { drop_shared(self.ptr) };
+        }
+    }
+}
+
+impl<T> Drop for BiasedArc<T> {
+    fn drop(&mut self) {
+        unsafe { drop_shared(self.ptr) };
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for BiasedRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for BiasedArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn biased_rc_should_work() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop(u32);
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let a = BiasedRc::new(DetectDrop(1));
+        let b = a.clone();
+        assert_eq!(BiasedRc::biased_count(&a), 2);
+        assert!(BiasedRc::ptr_eq(&a, &b));
+        // All local pointers share one count of the atomic counter.
+        assert_eq!(a.data().shared_count.load(Ordering::Relaxed), 1);
+
+        let shared = BiasedRc::to_shared(&a);
+        let t = thread::spawn(move || {
+            assert!(BiasedArc::to_local(&shared).is_none());
+            let shared2 = shared.clone();
+            shared2.0
+        });
+        assert_eq!(t.join().unwrap(), 1);
+
+        drop((a, b));
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn shared_outliving_local_should_work() {
+        let shared = {
+            let local = BiasedRc::new(String::from("hello"));
+            BiasedRc::to_shared(&local)
+        };
+        // The owner thread can turn it back into a local pointer.
+        let local = BiasedArc::to_local(&shared).unwrap();
+        assert_eq!(BiasedRc::biased_count(&local), 1);
+        assert_eq!(shared.data().shared_count.load(Ordering::Relaxed), 2);
+        drop(local);
+
+        let t = thread::spawn(move || shared.len());
+        assert_eq!(t.join().unwrap(), 5);
+    }
+
+    #[test]
+    fn concurrent_clones_should_work() {
+        let local = BiasedRc::new(vec![1, 2, 3]);
+        let shared = BiasedRc::to_shared(&local);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let shared = shared.clone();
+                s.spawn(move || {
+                    for _ in 0..1000 {
+                        let c = shared.clone();
+                        assert_eq!(c.len(), 3);
+                    }
+                });
+            }
+            for _ in 0..1000 {
+                let c = local.clone();
+                assert_eq!(c[0], 1);
+            }
+        });
+        drop(shared);
+        assert_eq!(BiasedRc::biased_count(&local), 1);
+        assert_eq!(local.data().shared_count.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,354 @@
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use crate::{backoff::Backoff, cache_padded::CachePadded, Lazy, ReadGuard, RwLock, WriteGuard};
+
+/// How many times the last revocation took, before readers may
+/// switch back to the fast path.
+const INHIBIT_MULTIPLIER: u64 = 9;
+
+/// A reader-biased `RwLock`, based on BRAVO (Biased Locking for
+/// Reader-Writer Locks, Dice and Kogan 2019).
+///
+/// While the lock is biased towards readers, a reader only increments one
+/// of several cache-padded counters, picked per thread, instead of all
+/// readers updating the same state. A writer first takes the underlying
+/// `RwLock`, then revokes the bias and waits for the counters to drain.
+/// Since revoking is slow, readers don't restore the bias until some
+/// time has passed, proportional to how long the last revocation took.
+pub struct BravoRwLock<T: ?Sized> {
+    /// Readers may use the counters while set.
+    read_bias: AtomicBool,
+    /// Nanoseconds since `START` before readers may set `read_bias` again.
+    inhibit_until: AtomicU64,
+    /// Number of fast path readers, spread over several counters.
+    readers: Box<[CachePadded<AtomicUsize>]>,
+    /// Used by writers and by readers while the bias is revoked.
+    lock: RwLock<()>,
+    value: UnsafeCell<T>,
+}
+
+pub struct BravoReadGuard<'a, T: ?Sized> {
+    lock: &'a BravoRwLock<T>,
+    token: ReadToken<'a>,
+}
+
+enum ReadToken<'a> {
+    /// The reader counter we've incremented.
+    Fast(&'a AtomicUsize),
+    /// Holding the underlying read lock.
+    Slow { _guard: ReadGuard<'a, ()> },
+}
+
+pub struct BravoWriteGuard<'a, T: ?Sized> {
+    lock: &'a BravoRwLock<T>,
+    _guard: WriteGuard<'a, ()>,
+}
+
+unsafe impl<T: ?Sized> Sync for BravoRwLock<T> where T: Send + Sync {}
+
+static START: Lazy<Instant> = Lazy::new(Instant::now);
+
+fn now() -> u64 {
+    START.elapsed().as_nanos() as u64
+}
+
+static NEXT_READER_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// Spreads threads over the reader counters round-robin.
+    static READER_INDEX: usize = NEXT_READER_INDEX.fetch_add(1, Ordering::Relaxed);
+}
+
+impl<T> BravoRwLock<T> {
+    pub fn new(value: T) -> Self {
+        let shards = std::thread::available_parallelism()
+            .map_or(8, |n| n.get())
+            .next_power_of_two();
+        Self {
+            read_bias: AtomicBool::new(true),
+            inhibit_until: AtomicU64::new(0),
+            readers: (0..shards)
+                .map(|_| CachePadded::new(AtomicUsize::new(0)))
+                .collect(),
+            lock: RwLock::new(()),
+            value: UnsafeCell::new(value),
+        }
+    }
+}
+
+impl<T: ?Sized> BravoRwLock<T> {
+    pub fn read(&self) -> BravoReadGuard<'_, T> {
+        if let Some(guard) = self.read_fast() {
+            return guard;
+        }
+        let guard = self.lock.read();
+        self.maybe_restore_bias();
+        BravoReadGuard {
+            lock: self,
+            token: ReadToken::Slow { _guard: guard },
+        }
+    }
+
+    /// Tries to acquire a read lock without blocking.
+    pub fn try_read(&self) -> Option<BravoReadGuard<'_, T>> {
+        if let Some(guard) = self.read_fast() {
+            return Some(guard);
+        }
+        let guard = self.lock.try_read()?;
+        self.maybe_restore_bias();
+        Some(BravoReadGuard {
+            lock: self,
+            token: ReadToken::Slow { _guard: guard },
+        })
+    }
+
+    pub fn write(&self) -> BravoWriteGuard<'_, T> {
+        let guard = self.lock.write();
+        if self.read_bias.load(Ordering::Relaxed) {
+            self.revoke_bias();
+        }
+        BravoWriteGuard {
+            lock: self,
+            _guard: guard,
+        }
+    }
+
+    /// Tries to acquire the write lock without blocking.
+    pub fn try_write(&self) -> Option<BravoWriteGuard<'_, T>> {
+        let guard = self.lock.try_write()?;
+        if self.read_bias.load(Ordering::Relaxed) {
+            self.read_bias.store(false, Ordering::SeqCst);
+            if self.readers.iter().any(|r| r.load(Ordering::SeqCst) != 0) {
+                // Nobody can have restored the bias, since we hold the
+                // underlying write lock, so give it back to the readers.
+                self.read_bias.store(true, Ordering::Relaxed);
+                return None;
+            }
+        }
+        Some(BravoWriteGuard {
+            lock: self,
+            _guard: guard,
+        })
+    }
+
+    fn read_fast(&self) -> Option<BravoReadGuard<'_, T>> {
+        if !self.read_bias.load(Ordering::Relaxed) {
+            return None;
+        }
+        let index = READER_INDEX.with(|i| *i) & (self.readers.len() - 1);
+        let counter = &*self.readers[index];
+        // SeqCst on both sides, so either the writer sees our increment
+        // or we see the revoked bias.
+        counter.fetch_add(1, Ordering::SeqCst);
+        if self.read_bias.load(Ordering::SeqCst) {
+            return Some(BravoReadGuard {
+                lock: self,
+                token: ReadToken::Fast(counter),
+            });
+        }
+        counter.fetch_sub(1, Ordering::Release);
+        None
+    }
+
+    /// Lets readers use the fast path again, unless still inhibited.
+    ///
+    /// Must be called with the underlying lock read locked,
+    /// so no writer can be revoking the bias.
+    fn maybe_restore_bias(&self) {
+        if !self.read_bias.load(Ordering::Relaxed)
+            && now() >= self.inhibit_until.load(Ordering::Relaxed)
+        {
+            // Release passes on what we acquired from the last writer to
+            // fast path readers, which load the bias with SeqCst.
+            self.read_bias.store(true, Ordering::Release);
+        }
+    }
+
+    /// Must be called with the underlying lock write locked.
+    fn revoke_bias(&self) {
+        self.read_bias.store(false, Ordering::SeqCst);
+        let start = now();
+        for counter in self.readers.iter() {
+            let mut backoff = Backoff::new();
+            // SeqCst also acquires, matching the Release
+            // decrement when fast path readers are done.
+            while counter.load(Ordering::SeqCst) != 0 {
+                backoff.snooze();
+            }
+        }
+        let end = now();
+        self.inhibit_until
+            .store(end + (end - start) * INHIBIT_MULTIPLIER, Ordering::Relaxed);
+    }
+}
+
+impl<T: ?Sized> Drop for BravoReadGuard<'_, T> {
+    fn drop(&mut self) {
+        if let ReadToken::Fast(counter) = self.token {
+            counter.fetch_sub(1, Ordering::Release);
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for BravoReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Deref for BravoWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for BravoWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for BravoRwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("BravoRwLock");
+        // Never block in `Debug`, the lock might be held by the caller.
+        match self.try_read() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+impl<T: Default> Default for BravoRwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for BravoRwLock<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for BravoReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for BravoReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for BravoWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for BravoWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn one_thread_should_work() {
+        let lock = BravoRwLock::new(vec![1, 2, 3]);
+        let r1 = lock.read();
+        let r2 = lock.read();
+        assert!(matches!(r1.token, ReadToken::Fast(_)));
+        assert_eq!(r1.len() + r2.len(), 6);
+        assert!(lock.try_write().is_none());
+        drop((r1, r2));
+
+        lock.write().push(4);
+        // The bias was revoked, readers take the slow path for a while.
+        assert!(!lock.read_bias.load(Ordering::Relaxed));
+        assert_eq!(*lock.read(), [1, 2, 3, 4]);
+        assert_eq!(
+            format!("{lock:?}"),
+            "BravoRwLock { data: [1, 2, 3, 4], .. }"
+        );
+    }
+
+    #[test]
+    fn bias_should_be_restored_after_inhibit_window() {
+        let lock = BravoRwLock::new(0);
+        *lock.write() += 1;
+        lock.inhibit_until.store(0, Ordering::Relaxed);
+        // The first slow reader restores the bias for the next ones.
+        drop(lock.read());
+        assert!(matches!(lock.read().token, ReadToken::Fast(_)));
+    }
+
+    #[test]
+    fn fast_reader_should_see_write_after_bias_is_restored() {
+        let lock = BravoRwLock::new(Vec::new());
+        for i in 0..100 {
+            // Revoke the bias, and keep readers from restoring it.
+            drop(lock.write());
+            lock.inhibit_until.store(u64::MAX, Ordering::Relaxed);
+            thread::scope(|s| {
+                s.spawn(|| {
+                    lock.write().push(i);
+                    lock.inhibit_until.store(0, Ordering::Relaxed);
+                    drop(lock.read());
+                });
+                s.spawn(|| loop {
+                    let r = lock.read();
+                    if matches!(r.token, ReadToken::Fast(_)) {
+                        assert_eq!(r.last(), Some(&i));
+                        break;
+                    }
+                    drop(r);
+                    thread::yield_now();
+                });
+            });
+        }
+    }
+
+    #[test]
+    fn readers_should_never_see_partial_writes() {
+        let lock = BravoRwLock::new((0, 0));
+        thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    for _ in 0..2000 {
+                        let r = lock.read();
+                        assert_eq!(r.0, r.1);
+                    }
+                });
+            }
+            s.spawn(|| {
+                for _ in 0..200 {
+                    let mut w = lock.write();
+                    w.0 += 1;
+                    thread::yield_now();
+                    w.1 += 1;
+                }
+            });
+        });
+        assert_eq!(*lock.read(), (200, 200));
+    }
+}
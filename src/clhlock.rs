@@ -0,0 +1,232 @@
+use std::{
+    cell::{RefCell, UnsafeCell},
+    fmt,
+    ops::{Deref, DerefMut},
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+use crate::backoff::Backoff;
+
+/// A fair queue lock where every waiter spins on its predecessor's node.
+///
+/// Unlike `McsLock`, unlocking never waits for a successor to show up.
+/// A thread that got the lock takes over its predecessor's node for its
+/// next `lock()`, so locking doesn't allocate once the nodes are warm.
+pub struct ClhLock<T: ?Sized> {
+    /// The node of the last thread in the queue, null when unlocked.
+    tail: AtomicPtr<ClhNode>,
+    value: UnsafeCell<T>,
+}
+
+struct ClhNode {
+    /// Set while the owner of the node holds or waits for the lock.
+    locked: AtomicBool,
+}
+
+pub struct ClhLockGuard<'a, T: ?Sized> {
+    lock: &'a ClhLock<T>,
+    node: *mut ClhNode,
+}
+
+unsafe impl<T: ?Sized> Sync for ClhLock<T> where T: Send {}
+
+thread_local! {
+    /// Nodes this thread owns but doesn't queue with right now.
+    // Boxed, since other threads keep pointers to queued nodes.
+    #[allow(clippy::vec_box)]
+    static FREE_NODES: RefCell<Vec<Box<ClhNode>>> = const { RefCell::new(Vec::new()) };
+}
+
+impl ClhNode {
+    /// Takes a node from this thread's free list, or allocates one.
+    fn acquire() -> *mut ClhNode {
+        let node = FREE_NODES
+            .try_with(|nodes| nodes.borrow_mut().pop())
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| {
+                Box::new(ClhNode {
+                    locked: AtomicBool::new(true),
+                })
+            });
+        node.locked.store(true, Ordering::Relaxed);
+        Box::into_raw(node)
+    }
+
+    /// Puts a node nobody else references anymore on this thread's free list.
+    unsafe fn release(node: *mut ClhNode) {
+        let node = Box::from_raw(node);
+        // Just frees the node if the thread is exiting.
+        let _ = FREE_NODES.try_with(|nodes| nodes.borrow_mut().push(node));
+    }
+}
+
+impl<T> ClhLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            tail: AtomicPtr::new(ptr::null_mut()),
+            value: UnsafeCell::new(value),
+        }
+    }
+}
+
+impl<T: ?Sized> ClhLock<T> {
+    pub fn lock(&self) -> ClhLockGuard<'_, T> {
+        let node = ClhNode::acquire();
+        // Release publishes our node to the thread behind us.
+        let prev = self.tail.swap(node, Ordering::AcqRel);
+        if !prev.is_null() {
+            unsafe {
+                let mut backoff = Backoff::new();
+                // Acquire matches the Release store in `drop`.
+                while (*prev).locked.load(Ordering::Acquire) {
+                    backoff.snooze();
+                }
+                // Its previous owner doesn't touch it after unlocking,
+                // so the node is ours now.
+                ClhNode::release(prev);
+            }
+        }
+        ClhLockGuard { lock: self, node }
+    }
+
+    pub fn try_lock(&self) -> Option<ClhLockGuard<'_, T>> {
+        if !self.tail.load(Ordering::Relaxed).is_null() {
+            return None;
+        }
+        let node = ClhNode::acquire();
+        match self.tail.compare_exchange(
+            ptr::null_mut(),
+            node,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => Some(ClhLockGuard { lock: self, node }),
+            Err(_) => {
+                unsafe { ClhNode::release(node) };
+                None
+            }
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for ClhLockGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            // Nobody queued behind us, so nobody else saw our node.
+            if self
+                .lock
+                .tail
+                .compare_exchange(
+                    self.node,
+                    ptr::null_mut(),
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+            {
+                ClhNode::release(self.node);
+                return;
+            }
+            // The next thread takes over the node once it sees this.
+            (*self.node).locked.store(false, Ordering::Release);
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for ClhLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: The guard means we hold the lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for ClhLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for ClhLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("ClhLock");
+        // Never spin in `Debug`, the lock might be held by the caller.
+        match self.try_lock() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+impl<T: Default> Default for ClhLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for ClhLock<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for ClhLockGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for ClhLockGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn multi_threads_should_work() {
+        let lock = ClhLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *lock.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*lock.lock(), 4000);
+    }
+
+    #[test]
+    fn try_lock_and_debug_should_work() {
+        let lock = ClhLock::new(1);
+        assert_eq!(format!("{lock:?}"), "ClhLock { data: 1, .. }");
+        let guard = lock.try_lock().unwrap();
+        assert!(lock.try_lock().is_none());
+        assert_eq!(format!("{lock:?}"), "ClhLock { data: <locked>, .. }");
+        assert_eq!(guard.to_string(), "1");
+    }
+
+    #[test]
+    fn unsized_value_should_work() {
+        let lock: &ClhLock<[u8]> = &ClhLock::new([0; 4]);
+        thread::scope(|s| {
+            for i in 0..4 {
+                s.spawn(move || lock.lock()[i] = i as u8);
+            }
+        });
+        let guard = lock.lock();
+        assert_eq!(&*guard, &[0, 1, 2, 3]);
+        assert_eq!(format!("{guard:?}"), "[0, 1, 2, 3]");
+    }
+}
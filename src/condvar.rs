@@ -1,4 +1,7 @@
-use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::{
+    fmt,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+};
 
 use atomic_wait::{wait, wake_all, wake_one};
 
@@ -31,7 +34,7 @@ impl Condvar {
         }
     }
 
-    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
         self.num_waiters.fetch_add(1, Ordering::Relaxed);
         let counter_value = self.counter.load(Ordering::Relaxed);
 
@@ -56,6 +59,12 @@ impl Default for Condvar {
     }
 }
 
+impl fmt::Debug for Condvar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Condvar").finish_non_exhaustive()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{collections::VecDeque, thread, time::Duration};
@@ -0,0 +1,387 @@
+use std::{
+    cell::{Cell, UnsafeCell},
+    fmt,
+    marker::PhantomData,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{fence, AtomicIsize, AtomicPtr, Ordering},
+        Arc,
+    },
+};
+
+use crate::{cache_padded::CachePadded, epoch};
+
+/// Capacity of a new buffer.
+const MIN_CAP: usize = 16;
+
+/// Buffers at least this big are freed as soon as possible.
+const FLUSH_THRESHOLD: usize = 1 << 10;
+
+/// The result of stealing.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Steal<T> {
+    /// The deque was empty.
+    Empty,
+    /// We stole a value.
+    Success(T),
+    /// Lost a race with another thread, worth trying again.
+    Retry,
+}
+
+impl<T> Steal<T> {
+    /// Returns the stolen value, if any.
+    pub fn success(self) -> Option<T> {
+        match self {
+            Steal::Success(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn is_retry(&self) -> bool {
+        matches!(self, Steal::Retry)
+    }
+}
+
+/// A circular buffer, indexed by positions modulo its power of two capacity.
+struct Buffer<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+}
+
+impl<T> Buffer<T> {
+    fn alloc(cap: usize) -> *mut Buffer<T> {
+        debug_assert!(cap.is_power_of_two());
+        Box::into_raw(Box::new(Buffer {
+            slots: (0..cap)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+        }))
+    }
+
+    fn cap(&self) -> usize {
+        self.slots.len()
+    }
+
+    fn at(&self, index: isize) -> *mut MaybeUninit<T> {
+        self.slots[index as usize & (self.cap() - 1)].get()
+    }
+
+    /// Reads the value at `index` without taking ownership of it.
+    unsafe fn read(&self, index: isize) -> MaybeUninit<T> {
+        self.at(index).read()
+    }
+
+    unsafe fn write(&self, index: isize, value: T) {
+        (*self.at(index)).write(value);
+    }
+}
+
+struct Inner<T> {
+    /// Where stealers take from.
+    top: CachePadded<AtomicIsize>,
+    /// Where the worker pushes and pops.
+    bottom: CachePadded<AtomicIsize>,
+    /// Old buffers are freed through epoch-based reclamation,
+    /// since stealers might still be reading them.
+    buffer: CachePadded<AtomicPtr<Buffer<T>>>,
+}
+
+unsafe impl<T: Send> Send for Inner<T> {}
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+/// The owner side of a work-stealing deque, based on Chase and Lev's
+/// "Dynamic Circular Work-Stealing Deque" (2005), with the memory
+/// orderings from Lê et al. (2013).
+///
+/// The owner pushes and pops at the bottom, like a stack, while any
+/// number of `Stealer`s take the oldest values from the top.
+pub struct Worker<T> {
+    inner: Arc<Inner<T>>,
+    /// Our copy of `inner.buffer`, only the worker replaces it.
+    buffer: Cell<*mut Buffer<T>>,
+    /// Only one thread may push and pop.
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+unsafe impl<T: Send> Send for Worker<T> {}
+
+/// Steals values from the top of a `Worker`'s deque.
+pub struct Stealer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T> Worker<T> {
+    pub fn new() -> Self {
+        let buffer = Buffer::alloc(MIN_CAP);
+        Self {
+            inner: Arc::new(Inner {
+                top: CachePadded::new(AtomicIsize::new(0)),
+                bottom: CachePadded::new(AtomicIsize::new(0)),
+                buffer: CachePadded::new(AtomicPtr::new(buffer)),
+            }),
+            buffer: Cell::new(buffer),
+            _not_sync: PhantomData,
+        }
+    }
+
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer {
+            inner: self.inner.clone(),
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        let b = self.inner.bottom.load(Ordering::Relaxed);
+        let t = self.inner.top.load(Ordering::Acquire);
+        let mut buffer = self.buffer.get();
+        let cap = unsafe { (*buffer).cap() };
+        if b - t >= cap as isize {
+            self.resize(cap * 2);
+            buffer = self.buffer.get();
+        }
+        unsafe { (*buffer).write(b, value) };
+        // Release publishes the value to stealers that see the new bottom.
+        fence(Ordering::Release);
+        self.inner.bottom.store(b + 1, Ordering::Relaxed);
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let b = self.inner.bottom.load(Ordering::Relaxed) - 1;
+        self.inner.bottom.store(b, Ordering::Relaxed);
+        // Pairs with the fence in `steal`: either the stealer sees the
+        // smaller bottom, or we see its bigger top.
+        fence(Ordering::SeqCst);
+        let t = self.inner.top.load(Ordering::Relaxed);
+
+        if t > b {
+            // Empty, restore the bottom.
+            self.inner.bottom.store(b + 1, Ordering::Relaxed);
+            return None;
+        }
+        let value = unsafe { (*self.buffer.get()).read(b) };
+        if t == b {
+            // The last value, race the stealers for it.
+            let won = self
+                .inner
+                .top
+                .compare_exchange(t, t + 1, Ordering::SeqCst, Ordering::Relaxed)
+                .is_ok();
+            self.inner.bottom.store(b + 1, Ordering::Relaxed);
+            if !won {
+                // A stealer owns the value now.
+                return None;
+            }
+        }
+        Some(unsafe { value.assume_init() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Moves the values to a new buffer with capacity `cap`.
+    fn resize(&self, cap: usize) {
+        let b = self.inner.bottom.load(Ordering::Relaxed);
+        let t = self.inner.top.load(Ordering::Relaxed);
+        let old = self.buffer.get();
+        let new = Buffer::alloc(cap);
+        for i in t..b {
+            // Safety: Only stealers race us for these values, and
+            // they read them from either buffer.
+            unsafe { (*new).at(i).write((*old).read(i)) };
+        }
+
+        let guard = epoch::pin();
+        self.buffer.set(new);
+        // Release publishes the copied values.
+        self.inner.buffer.store(new, Ordering::Release);
+        // Safety: New stealers load the new buffer, so only the ones
+        // pinned right now might still read the old one.
+        unsafe { guard.defer_destroy(old) };
+        if cap >= FLUSH_THRESHOLD {
+            guard.flush();
+        }
+    }
+}
+
+impl<T> Stealer<T> {
+    /// Steals the value at the top of the deque.
+    pub fn steal(&self) -> Steal<T> {
+        // Pin first, so the buffer we load below can't be freed.
+        let _guard = epoch::pin();
+        let t = self.inner.top.load(Ordering::Acquire);
+        // Pairs with the fence in `Worker::pop`.
+        fence(Ordering::SeqCst);
+        let b = self.inner.bottom.load(Ordering::Acquire);
+        if b <= t {
+            return Steal::Empty;
+        }
+
+        // Acquire matches the Release in `Worker::resize`.
+        let buffer = self.inner.buffer.load(Ordering::Acquire);
+        let value = unsafe { (*buffer).read(t) };
+        if self
+            .inner
+            .top
+            .compare_exchange(t, t + 1, Ordering::SeqCst, Ordering::Relaxed)
+            .is_err()
+        {
+            // Someone else took it, our copy isn't ours to drop.
+            return Steal::Retry;
+        }
+        Steal::Success(unsafe { value.assume_init() })
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Inner<T> {
+    fn len(&self) -> usize {
+        let t = self.top.load(Ordering::SeqCst);
+        let b = self.bottom.load(Ordering::SeqCst);
+        (b - t).max(0) as usize
+    }
+}
+
+impl<T> Drop for Inner<T> {
+    fn drop(&mut self) {
+        let t = *self.top.get_mut();
+        let b = *self.bottom.get_mut();
+        let buffer = *self.buffer.get_mut();
+        unsafe {
+            for i in t..b {
+                (*buffer).read(i).assume_init_drop();
+            }
+            drop(Box::from_raw(buffer));
+        }
+    }
+}
+
+impl<T> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Default for Worker<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Worker<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Worker")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for Stealer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stealer")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, AtomicUsize},
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn one_thread_should_work() {
+        let worker = Worker::new();
+        let stealer = worker.stealer();
+        assert_eq!(stealer.steal(), Steal::Empty);
+        // Enough values to grow the buffer a few times.
+        for i in 0..100 {
+            worker.push(i);
+        }
+        assert_eq!(worker.len(), 100);
+        assert_eq!(worker.pop(), Some(99));
+        assert_eq!(stealer.steal(), Steal::Success(0));
+        assert_eq!(stealer.steal().success(), Some(1));
+        for i in (2..99).rev() {
+            assert_eq!(worker.pop(), Some(i));
+        }
+        assert_eq!(worker.pop(), None);
+        assert_eq!(stealer.steal(), Steal::Empty);
+    }
+
+    #[test]
+    fn owner_and_stealers_should_take_each_value_once() {
+        const N: usize = 20000;
+        let worker = Worker::new();
+        let taken: Vec<AtomicBool> = (0..N).map(|_| AtomicBool::new(false)).collect();
+        let num_taken = AtomicUsize::new(0);
+        let take = |v: usize| {
+            assert!(!taken[v].swap(true, Ordering::Relaxed), "{v} taken twice");
+            num_taken.fetch_add(1, Ordering::Relaxed);
+        };
+
+        thread::scope(|s| {
+            for _ in 0..3 {
+                let stealer = worker.stealer();
+                let take = &take;
+                let num_taken = &num_taken;
+                s.spawn(move || {
+                    while num_taken.load(Ordering::Relaxed) < N {
+                        match stealer.steal() {
+                            Steal::Success(v) => take(v),
+                            Steal::Empty => thread::yield_now(),
+                            Steal::Retry => {}
+                        }
+                    }
+                });
+            }
+
+            // Push in bursts so the buffer grows, and pop some of it back.
+            let mut next = 0;
+            while next < N {
+                for _ in 0..100.min(N - next) {
+                    worker.push(next);
+                    next += 1;
+                }
+                for _ in 0..30 {
+                    if let Some(v) = worker.pop() {
+                        take(v);
+                    }
+                }
+            }
+            while let Some(v) = worker.pop() {
+                take(v);
+            }
+        });
+        assert_eq!(num_taken.load(Ordering::Relaxed), N);
+    }
+
+    #[test]
+    fn drop_should_drop_remaining_values() {
+        let value = Arc::new(());
+        let worker = Worker::new();
+        for _ in 0..40 {
+            worker.push(value.clone());
+        }
+        drop(worker.pop());
+        drop(worker.stealer().steal());
+        drop(worker);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
@@ -0,0 +1,430 @@
+//! Epoch-based memory reclamation for the lock-free collections.
+//!
+//! A thread pins itself before reading shared pointers, and memory unlinked
+//! from a data structure is only freed once every thread that was pinned
+//! at that point has unpinned. To know when that's the case, there's a
+//! global epoch that can only be advanced when all pinned threads have
+//! observed the current one: garbage retired in epoch `e` can be freed
+//! once the global epoch reaches `e + 2`.
+//!
+//! Retired pointers go to a thread-local bag first. Full bags are sealed
+//! with the current epoch and freed by the same thread once expired, or
+//! by any thread if their thread exited.
+
+use std::{
+    cell::{Cell, RefCell},
+    marker::PhantomData,
+    mem, ptr,
+    sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+};
+
+use crate::Mutex;
+
+/// Number of retired pointers in a bag before it gets sealed.
+const BAG_SIZE: usize = 64;
+
+/// Try to free garbage every this many pins.
+const PINS_BETWEEN_COLLECT: usize = 128;
+
+/// The global epoch. Only ever increases.
+static EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+/// All participants that ever existed. Entries are reused, never freed.
+static PARTICIPANTS: AtomicPtr<Participant> = AtomicPtr::new(ptr::null_mut());
+
+/// Sealed bags left behind by exited threads.
+static ORPHANS: Mutex<Vec<SealedBag>> = Mutex::new(Vec::new());
+
+struct Participant {
+    /// The epoch this participant is pinned in, shifted left by one,
+    /// with the lowest bit set while pinned.
+    epoch: AtomicUsize,
+    /// Set while a thread owns this participant.
+    in_use: AtomicBool,
+    next: *const Participant,
+}
+
+/// A pointer and the function to free it.
+struct Deferred {
+    ptr: *mut u8,
+    destroy: unsafe fn(*mut u8),
+}
+
+// Safety: `Guard::defer_destroy` requires the pointee to be `Send`.
+unsafe impl Send for Deferred {}
+
+struct SealedBag {
+    epoch: usize,
+    deferred: Vec<Deferred>,
+}
+
+struct Local {
+    participant: &'static Participant,
+    /// Number of `Guard`s alive on this thread.
+    guard_count: Cell<usize>,
+    pin_count: Cell<usize>,
+    bag: RefCell<Vec<Deferred>>,
+    sealed: RefCell<Vec<SealedBag>>,
+}
+
+thread_local! {
+    static LOCAL: Local = Local::register();
+}
+
+/// Keeps the current thread pinned. Pointers loaded from the
+/// data structures stay valid while it's alive.
+pub(crate) struct Guard {
+    /// Guards must be dropped on the thread that created them.
+    _no_send: PhantomData<*const ()>,
+}
+
+/// Pins the current thread.
+///
+/// # Panics
+///
+/// Panics if called while the thread-local state is being destroyed.
+pub(crate) fn pin() -> Guard {
+    LOCAL.with(|local| local.pin());
+    Guard {
+        _no_send: PhantomData,
+    }
+}
+
+impl Guard {
+    /// Frees `ptr` as a `Box<T>` once no pinned thread can access it anymore.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Box::into_raw`, must already be unreachable for
+    /// threads that pin from now on, and must not be retired twice.
+    /// The `Box<T>` will be dropped on an arbitrary thread, so it must be
+    /// fine to send it.
+    pub(crate) unsafe fn defer_destroy<T>(&self, ptr: *mut T) {
+        unsafe fn destroy<T>(ptr: *mut u8) {
+            drop(Box::from_raw(ptr as *mut T));
+        }
+        LOCAL.with(|local| {
+            local.bag.borrow_mut().push(Deferred {
+                ptr: ptr as *mut u8,
+                destroy: destroy::<T>,
+            });
+            if local.bag.borrow().len() >= BAG_SIZE {
+                local.seal_bag();
+            }
+        });
+    }
+
+    /// Seals the current bag and frees whatever garbage has expired.
+    pub(crate) fn flush(&self) {
+        LOCAL.with(|local| {
+            local.seal_bag();
+            local.collect();
+        });
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        LOCAL.with(|local| local.unpin());
+    }
+}
+
+impl Local {
+    fn register() -> Local {
+        // Reuse the participant of an exited thread if there is one.
+        let mut p = PARTICIPANTS.load(Ordering::Acquire);
+        while !p.is_null() {
+            let participant = unsafe { &*p };
+            if participant
+                .in_use
+                .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Local::new(participant);
+            }
+            p = participant.next as *mut Participant;
+        }
+
+        let participant = Box::leak(Box::new(Participant {
+            epoch: AtomicUsize::new(0),
+            in_use: AtomicBool::new(true),
+            next: ptr::null(),
+        }));
+        let mut head = PARTICIPANTS.load(Ordering::Relaxed);
+        loop {
+            participant.next = head;
+            // Release publishes the participant to the threads iterating the list.
+            match PARTICIPANTS.compare_exchange_weak(
+                head,
+                participant,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Local::new(participant),
+                Err(e) => head = e,
+            }
+        }
+    }
+
+    fn new(participant: &'static Participant) -> Local {
+        Local {
+            participant,
+            guard_count: Cell::new(0),
+            pin_count: Cell::new(0),
+            bag: RefCell::new(Vec::new()),
+            sealed: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn pin(&self) {
+        let count = self.guard_count.get();
+        self.guard_count.set(count + 1);
+        if count > 0 {
+            return;
+        }
+        let epoch = EPOCH.load(Ordering::Relaxed);
+        self.participant
+            .epoch
+            .store(epoch << 1 | 1, Ordering::Relaxed);
+        // Make sure the pin is visible to `try_advance` before we read
+        // any shared pointers.
+        fence(Ordering::SeqCst);
+
+        let pins = self.pin_count.get().wrapping_add(1);
+        self.pin_count.set(pins);
+        if pins % PINS_BETWEEN_COLLECT == 0 {
+            self.collect();
+        }
+    }
+
+    fn unpin(&self) {
+        let count = self.guard_count.get() - 1;
+        self.guard_count.set(count);
+        if count == 0 {
+            // Release, so everything we did while pinned happens before
+            // the garbage we might have seen gets freed.
+            self.participant.epoch.store(0, Ordering::Release);
+        }
+    }
+
+    fn seal_bag(&self) {
+        let deferred = mem::take(&mut *self.bag.borrow_mut());
+        if deferred.is_empty() {
+            return;
+        }
+        // Everything in the bag was unlinked before this point,
+        // so only threads pinned in this epoch or earlier can see it.
+        fence(Ordering::SeqCst);
+        let epoch = EPOCH.load(Ordering::Relaxed);
+        self.sealed.borrow_mut().push(SealedBag { epoch, deferred });
+    }
+
+    fn collect(&self) {
+        let epoch = try_advance();
+        let expired: Vec<SealedBag> = {
+            let mut sealed = self.sealed.borrow_mut();
+            let (expired, alive) = mem::take(&mut *sealed)
+                .into_iter()
+                .partition(|bag| bag.is_expired(epoch));
+            *sealed = alive;
+            expired
+        };
+        expired.into_iter().for_each(SealedBag::destroy);
+
+        // Don't wait for other threads collecting orphans.
+        if let Some(mut orphans) = ORPHANS.try_lock() {
+            let expired: Vec<SealedBag>;
+            (expired, *orphans) = mem::take(&mut *orphans)
+                .into_iter()
+                .partition(|bag| bag.is_expired(epoch));
+            drop(orphans);
+            expired.into_iter().for_each(SealedBag::destroy);
+        }
+    }
+}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        self.seal_bag();
+        let sealed = mem::take(self.sealed.get_mut());
+        if !sealed.is_empty() {
+            ORPHANS.lock().extend(sealed);
+        }
+        self.participant.epoch.store(0, Ordering::Release);
+        self.participant.in_use.store(false, Ordering::Release);
+    }
+}
+
+impl SealedBag {
+    fn is_expired(&self, global_epoch: usize) -> bool {
+        // Signed, so a bag sealed in a newer epoch than the one we've
+        // read is never expired.
+        global_epoch.wrapping_sub(self.epoch) as isize >= 2
+    }
+
+    fn destroy(self) {
+        for deferred in self.deferred {
+            // Safety: Guaranteed by `defer_destroy`, and the bag has expired.
+            unsafe { (deferred.destroy)(deferred.ptr) };
+        }
+    }
+}
+
+/// Advances the global epoch if all pinned threads are in the current one.
+///
+/// Returns the global epoch.
+fn try_advance() -> usize {
+    let epoch = EPOCH.load(Ordering::Relaxed);
+    // Matches the SeqCst fence in `pin`: either we see a thread pinned,
+    // or it sees the epoch we're about to advance from (or a later one).
+    fence(Ordering::SeqCst);
+
+    let mut p = PARTICIPANTS.load(Ordering::Acquire);
+    while !p.is_null() {
+        let participant = unsafe { &*p };
+        let e = participant.epoch.load(Ordering::Relaxed);
+        if e & 1 == 1 && e != epoch << 1 | 1 {
+            return epoch;
+        }
+        p = participant.next as *mut Participant;
+    }
+    // Matches the Release unpins, so their accesses happen
+    // before the garbage of this epoch gets freed.
+    fence(Ordering::Acquire);
+
+    // A racing thread might have advanced it already, possibly more than
+    // once, so only move forward from the epoch we've checked.
+    let next = epoch.wrapping_add(1);
+    match EPOCH.compare_exchange(epoch, next, Ordering::Release, Ordering::Relaxed) {
+        Ok(_) => next,
+        Err(current) => current,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::AtomicU64, mpsc},
+        thread,
+    };
+
+    use super::*;
+
+    struct DetectDrop<'a>(&'a AtomicBool);
+
+    impl Drop for DetectDrop<'_> {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn flush_until(done: impl Fn() -> bool) -> bool {
+        for _ in 0..1000 {
+            pin().flush();
+            if done() {
+                return true;
+            }
+            thread::yield_now();
+        }
+        false
+    }
+
+    #[test]
+    fn deferred_destroy_should_run_eventually() {
+        static DROPPED: AtomicBool = AtomicBool::new(false);
+        let guard = pin();
+        unsafe { guard.defer_destroy(Box::into_raw(Box::new(DetectDrop(&DROPPED)))) };
+        guard.flush();
+        // We're still pinned, so it can't have been freed.
+        assert!(!DROPPED.load(Ordering::Relaxed));
+        drop(guard);
+
+        assert!(flush_until(|| DROPPED.load(Ordering::Relaxed)));
+    }
+
+    #[test]
+    fn pinned_thread_should_block_reclamation() {
+        static DROPPED: AtomicBool = AtomicBool::new(false);
+        let (pinned_tx, pinned_rx) = mpsc::channel();
+        let (unpin_tx, unpin_rx) = mpsc::channel::<()>();
+
+        let t = thread::spawn(move || {
+            let _guard = pin();
+            pinned_tx.send(()).unwrap();
+            unpin_rx.recv().unwrap();
+        });
+        pinned_rx.recv().unwrap();
+
+        unsafe { pin().defer_destroy(Box::into_raw(Box::new(DetectDrop(&DROPPED)))) };
+        for _ in 0..10 {
+            pin().flush();
+        }
+        assert!(!DROPPED.load(Ordering::Relaxed));
+
+        unpin_tx.send(()).unwrap();
+        t.join().unwrap();
+        assert!(flush_until(|| DROPPED.load(Ordering::Relaxed)));
+    }
+
+    #[test]
+    fn garbage_of_exited_threads_should_be_freed() {
+        static DROPPED: AtomicBool = AtomicBool::new(false);
+        thread::spawn(|| unsafe {
+            pin().defer_destroy(Box::into_raw(Box::new(DetectDrop(&DROPPED))));
+        })
+        .join()
+        .unwrap();
+        assert!(flush_until(|| DROPPED.load(Ordering::Relaxed)));
+    }
+
+    #[test]
+    fn concurrent_retire_should_not_free_pinned_memory() {
+        const MAGIC: u64 = 0x5eed_cafe;
+        static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+        static FREED: AtomicUsize = AtomicUsize::new(0);
+
+        struct Tracked(AtomicU64);
+
+        impl Tracked {
+            fn new() -> *mut Tracked {
+                ALLOCATED.fetch_add(1, Ordering::Relaxed);
+                Box::into_raw(Box::new(Tracked(AtomicU64::new(MAGIC))))
+            }
+        }
+
+        impl Drop for Tracked {
+            fn drop(&mut self) {
+                // Poison, so readers notice if it's freed too early.
+                self.0.store(0, Ordering::Relaxed);
+                FREED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let shared = AtomicPtr::new(Tracked::new());
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for i in 0..2000 {
+                        let guard = pin();
+                        let p = shared.load(Ordering::Acquire);
+                        assert_eq!(unsafe { (*p).0.load(Ordering::Relaxed) }, MAGIC);
+                        if i % 4 == 0 {
+                            let old = shared.swap(Tracked::new(), Ordering::AcqRel);
+                            unsafe { guard.defer_destroy(old) };
+                        }
+                        thread::yield_now();
+                        assert_eq!(unsafe { (*p).0.load(Ordering::Relaxed) }, MAGIC);
+                        if i % 64 == 0 {
+                            guard.flush();
+                        }
+                    }
+                });
+            }
+        });
+
+        drop(unsafe { Box::from_raw(shared.load(Ordering::Relaxed)) });
+        assert!(flush_until(|| {
+            FREED.load(Ordering::Relaxed) == ALLOCATED.load(Ordering::Relaxed)
+        }));
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+use atomic_wait::{wait, wake_all, wake_one};
+
+use crate::futex;
+
+/// An event that stays set until it's reset, releasing all waiting threads.
+pub struct ManualResetEvent {
+    /// 0: not set
+    /// 1: set
+    /// 2: not set, other threads waiting
+    state: AtomicU32,
+}
+
+/// An event that releases a single waiting thread, then resets itself.
+///
+/// If nobody is waiting, the event stays set until the next thread waits.
+pub struct AutoResetEvent {
+    /// 0: not set
+    /// 1: set
+    state: AtomicU32,
+    num_waiters: AtomicU32,
+}
+
+impl ManualResetEvent {
+    pub const fn new(set: bool) -> Self {
+        Self {
+            state: AtomicU32::new(set as u32),
+        }
+    }
+
+    /// Sets the event, waking up all waiting threads.
+    pub fn set(&self) {
+        // Release matches the Acquire loads in `wait`.
+        if self.state.swap(1, Ordering::Release) == 2 {
+            wake_all(&self.state);
+        }
+    }
+
+    pub fn reset(&self) {
+        let _ = self
+            .state
+            .compare_exchange(1, 0, Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.state.load(Ordering::Acquire) == 1
+    }
+
+    /// Blocks until the event is set.
+    pub fn wait(&self) {
+        self.wait_until(None);
+    }
+
+    /// Blocks until the event is set, giving up after `timeout`.
+    ///
+    /// Returns `true` if the event was set. A timeout too large to
+    /// represent waits forever.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        self.wait_until(Instant::now().checked_add(timeout))
+    }
+
+    fn wait_until(&self, deadline: Option<Instant>) -> bool {
+        loop {
+            let state = self.state.load(Ordering::Acquire);
+            if state == 1 {
+                return true;
+            }
+            // Let `set` know it has to wake us.
+            if state == 0
+                && self
+                    .state
+                    .compare_exchange(0, 2, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_err()
+            {
+                continue;
+            }
+            match deadline {
+                None => wait(&self.state, 2),
+                Some(deadline) => {
+                    if !futex::wait_until(&self.state, 2, deadline) {
+                        return self.is_set();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl AutoResetEvent {
+    pub const fn new(set: bool) -> Self {
+        Self {
+            state: AtomicU32::new(set as u32),
+            num_waiters: AtomicU32::new(0),
+        }
+    }
+
+    /// Sets the event, waking up one waiting thread.
+    pub fn set(&self) {
+        // SeqCst on both sides, so either we see the waiter or
+        // the waiter sees the event set before going to sleep.
+        if self.state.swap(1, Ordering::SeqCst) == 0 && self.num_waiters.load(Ordering::SeqCst) > 0
+        {
+            wake_one(&self.state);
+        }
+    }
+
+    pub fn reset(&self) {
+        self.state.store(0, Ordering::Relaxed);
+    }
+
+    /// Blocks until the event is set, and resets it.
+    pub fn wait(&self) {
+        self.wait_until(None);
+    }
+
+    /// Blocks until the event is set and resets it, giving up after `timeout`.
+    ///
+    /// Returns `true` if the event was set. A timeout too large to
+    /// represent waits forever.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        self.wait_until(Instant::now().checked_add(timeout))
+    }
+
+    /// Resets the event if it's set, without blocking.
+    ///
+    /// Returns `true` if the event was set.
+    pub fn try_wait(&self) -> bool {
+        // Acquire matches the Release part of the swap in `set`.
+        self.state
+            .compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    fn wait_until(&self, deadline: Option<Instant>) -> bool {
+        loop {
+            if self.try_wait() {
+                return true;
+            }
+            self.num_waiters.fetch_add(1, Ordering::SeqCst);
+            let timed_out = if self.state.load(Ordering::SeqCst) == 1 {
+                false
+            } else if let Some(deadline) = deadline {
+                !futex::wait_until(&self.state, 0, deadline)
+            } else {
+                wait(&self.state, 0);
+                false
+            };
+            self.num_waiters.fetch_sub(1, Ordering::Relaxed);
+            if timed_out {
+                return self.try_wait();
+            }
+        }
+    }
+}
+
+impl Default for ManualResetEvent {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl Default for AutoResetEvent {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+impl fmt::Debug for ManualResetEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ManualResetEvent")
+            .field("set", &self.is_set())
+            .finish()
+    }
+}
+
+impl fmt::Debug for AutoResetEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AutoResetEvent")
+            .field("set", &(self.state.load(Ordering::Relaxed) == 1))
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicUsize, thread};
+
+    use super::*;
+
+    #[test]
+    fn manual_reset_event_should_release_all_waiters() {
+        let event = ManualResetEvent::new(false);
+        let released = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    event.wait();
+                    released.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+            thread::sleep(Duration::from_millis(10));
+            assert_eq!(released.load(Ordering::Relaxed), 0);
+            event.set();
+        });
+        assert_eq!(released.load(Ordering::Relaxed), 3);
+
+        // Stays set until reset.
+        assert!(event.wait_timeout(Duration::ZERO));
+        event.reset();
+        assert!(!event.is_set());
+        assert!(!event.wait_timeout(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn auto_reset_event_should_release_one_waiter() {
+        let event = AutoResetEvent::new(false);
+        let released = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    event.wait();
+                    released.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+            for n in 1..=3 {
+                event.set();
+                while released.load(Ordering::Relaxed) < n {
+                    thread::yield_now();
+                }
+                thread::sleep(Duration::from_millis(10));
+                assert_eq!(released.load(Ordering::Relaxed), n);
+            }
+        });
+    }
+
+    #[test]
+    fn auto_reset_event_should_stay_set_without_waiters() {
+        let event = AutoResetEvent::new(false);
+        event.set();
+        event.set();
+        assert!(event.try_wait());
+        assert!(!event.try_wait());
+        assert!(!event.wait_timeout(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn huge_timeout_should_wait_forever() {
+        let manual = ManualResetEvent::new(false);
+        let auto = AutoResetEvent::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                manual.set();
+                auto.set();
+            });
+            assert!(manual.wait_timeout(Duration::MAX));
+            assert!(auto.wait_timeout(Duration::MAX));
+        });
+    }
+}
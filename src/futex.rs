@@ -0,0 +1,56 @@
+//! Futex waits with a timeout, which `atomic_wait` doesn't provide.
+//!
+//! Waking is still done with `atomic_wait::wake_one`/`wake_all`.
+
+use std::{
+    sync::atomic::AtomicU32,
+    time::{Duration, Instant},
+};
+
+/// Blocks while `atomic` holds `value`, until woken or until `deadline`.
+///
+/// Like `atomic_wait::wait`, this might return spuriously.
+/// Returns `false` if the deadline has passed.
+pub(crate) fn wait_until(atomic: &AtomicU32, value: u32, deadline: Instant) -> bool {
+    let now = Instant::now();
+    if now >= deadline {
+        return false;
+    }
+    wait_timeout(atomic, value, deadline - now);
+    Instant::now() < deadline
+}
+
+#[cfg(target_os = "linux")]
+fn wait_timeout(atomic: &AtomicU32, value: u32, timeout: Duration) {
+    let ts = libc::timespec {
+        tv_sec: timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as libc::c_long,
+    };
+    // Safety: The futex syscall only reads the atomic and the timespec.
+    // Errors (EAGAIN, EINTR, ETIMEDOUT) all mean "go check the value again".
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            atomic.as_ptr(),
+            libc::FUTEX_WAIT | libc::FUTEX_PRIVATE_FLAG,
+            value,
+            &ts as *const libc::timespec,
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn wait_timeout(atomic: &AtomicU32, value: u32, timeout: Duration) {
+    // No portable timed futex, poll instead.
+    let deadline = Instant::now().checked_add(timeout);
+    while atomic.load(std::sync::atomic::Ordering::Relaxed) == value {
+        let now = Instant::now();
+        let left = match deadline {
+            Some(deadline) if now >= deadline => return,
+            Some(deadline) => deadline - now,
+            // Too far away to represent, just keep polling.
+            None => Duration::MAX,
+        };
+        std::thread::sleep(left.min(Duration::from_millis(1)));
+    }
+}
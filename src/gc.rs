@@ -0,0 +1,783 @@
+use std::{
+    cell::{Cell, UnsafeCell},
+    collections::VecDeque,
+    fmt,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ops::Deref,
+    ptr::NonNull,
+    sync::atomic::{fence, AtomicBool, AtomicU8, AtomicUsize, Ordering},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{Mutex, ReadGuard, RwLock};
+
+/// Types that can report the `Gc` pointers they own to the cycle collector.
+///
+/// # Safety
+///
+/// `trace` must visit each `Gc` owned by the value at most once, and must not
+/// visit any `Gc` it doesn't own (e.g. one behind a shared `Arc`), otherwise
+/// the collector may free values that are still reachable. Not visiting a
+/// `Gc` is safe, cycles through it just won't be collected.
+pub unsafe trait Trace {
+    fn trace(&self, tracer: &mut Tracer<'_>);
+}
+
+/// Passed to `Trace::trace` to visit the `Gc` pointers of a value.
+pub struct Tracer<'a> {
+    visit: &'a mut dyn FnMut(Node),
+}
+
+impl Tracer<'_> {
+    pub fn visit<T: Trace + Send + Sync + 'static>(&mut self, gc: &Gc<T>) {
+        (self.visit)(gc.node())
+    }
+}
+
+/// A reference counted pointer whose garbage cycles can be collected.
+///
+/// Cloning and dropping work like `Arc`. Additionally, when a `Gc` is
+/// dropped but the value is still referenced, the value is remembered as a
+/// possible root of a garbage cycle. `collect_cycles` (or a collector started
+/// with `spawn_collector`) then runs the synchronous trial deletion algorithm
+/// of Bacon and Rajan on those roots, freeing the cycles that are only
+/// referenced from within themselves.
+///
+/// The value is accessed through `Gc::borrow`. The collector only runs while
+/// no `GcRef` exists, so values never change while they're being traced.
+pub struct Gc<T: Trace + Send + Sync + 'static> {
+    ptr: NonNull<GcBox<T>>,
+    _marker: PhantomData<GcBox<T>>,
+}
+
+unsafe impl<T: Trace + Send + Sync + 'static> Send for Gc<T> {}
+unsafe impl<T: Trace + Send + Sync + 'static> Sync for Gc<T> {}
+
+/// A borrowed value of a `Gc`, blocking cycle collection while it's alive.
+pub struct GcRef<'a, T: Trace + Send + Sync + 'static> {
+    value: &'a T,
+    _guard: MutatorGuard,
+    /// Borrows are counted per thread, so it must be dropped where it was created.
+    _no_send: PhantomData<*const ()>,
+}
+
+struct GcBox<T: ?Sized> {
+    /// Number of `Gc`s. Used as the trial count during collection.
+    count: AtomicUsize,
+    /// See `BLACK`, `GRAY`, `WHITE` and `PURPLE`.
+    color: AtomicU8,
+    /// Whether this node is in `ROOTS`. A buffered node is freed by the
+    /// collector, even if its count drops to zero outside of a collection.
+    buffered: AtomicBool,
+    /// Whether the value has been dropped.
+    dropped: AtomicBool,
+    value: UnsafeCell<ManuallyDrop<T>>,
+}
+
+/// In use or free.
+const BLACK: u8 = 0;
+/// Possible member of a cycle, during collection.
+const GRAY: u8 = 1;
+/// Member of a garbage cycle, during collection.
+const WHITE: u8 = 2;
+/// Possible root of a cycle.
+const PURPLE: u8 = 3;
+
+/// A type erased pointer to a `GcBox`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Node(NonNull<GcBox<dyn Trace + Send + Sync>>);
+
+// Safety: Only created for `GcBox`es of `Send + Sync` values.
+unsafe impl Send for Node {}
+
+/// Possible roots of garbage cycles.
+static ROOTS: Mutex<Vec<Node>> = Mutex::new(Vec::new());
+
+/// Read locked by mutators while they use a `Gc`, write locked while
+/// collecting.
+///
+/// The collector only ever uses `try_write`, so readers are never blocked
+/// and may lock it recursively.
+static COLLECTOR: RwLock<()> = RwLock::new(());
+
+thread_local! {
+    /// Whether this thread is running the collector, which already
+    /// excludes all mutators.
+    static COLLECTING: Cell<bool> = const { Cell::new(false) };
+    /// The number of `GcRef`s alive on this thread.
+    static BORROWS: Cell<usize> = const { Cell::new(0) };
+    /// The number of `MutatorGuard`s holding `COLLECTOR` on this thread.
+    static MUTATING: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Keeps the collector from running.
+struct MutatorGuard {
+    guard: Option<ReadGuard<'static, ()>>,
+}
+
+impl MutatorGuard {
+    fn new() -> Self {
+        if COLLECTING.get() {
+            return MutatorGuard { guard: None };
+        }
+        let guard = COLLECTOR.read();
+        MUTATING.set(MUTATING.get() + 1);
+        MutatorGuard { guard: Some(guard) }
+    }
+}
+
+impl Drop for MutatorGuard {
+    fn drop(&mut self) {
+        if self.guard.is_some() {
+            MUTATING.set(MUTATING.get() - 1);
+        }
+    }
+}
+
+impl<T: Trace + Send + Sync + 'static> Gc<T> {
+    pub fn new(value: T) -> Self {
+        Gc {
+            ptr: NonNull::from(Box::leak(Box::new(GcBox {
+                count: AtomicUsize::new(1),
+                color: AtomicU8::new(BLACK),
+                buffered: AtomicBool::new(false),
+                dropped: AtomicBool::new(false),
+                value: UnsafeCell::new(ManuallyDrop::new(value)),
+            }))),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Borrows the value. Cycle collection waits until the `GcRef` is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value has already been collected, which can only be
+    /// observed from the `Drop` of another value in the same garbage cycle.
+    pub fn borrow(&self) -> GcRef<'_, T> {
+        let guard = MutatorGuard::new();
+        let data = self.data();
+        assert!(
+            !data.dropped.load(Ordering::Relaxed),
+            "Gc value accessed after being collected"
+        );
+        BORROWS.set(BORROWS.get() + 1);
+        GcRef {
+            // Safety: The value is alive, and can't be collected while
+            // we're holding the guard.
+            value: unsafe { &*data.value.get() },
+            _guard: guard,
+            _no_send: PhantomData,
+        }
+    }
+
+    /// Returns `true` if both `Gc`s point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    /// Gets the number of `Gc`s pointing to this allocation.
+    pub fn strong_count(this: &Self) -> usize {
+        this.data().count.load(Ordering::Relaxed)
+    }
+
+    fn data(&self) -> &GcBox<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+
+    fn node(&self) -> Node {
+        Node(self.ptr)
+    }
+}
+
+impl<T: Trace + Send + Sync + 'static> Clone for Gc<T> {
+    fn clone(&self) -> Self {
+        let _guard = MutatorGuard::new();
+        let data = self.data();
+        if data.count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+        // A node that's still in use can't be the root of a garbage cycle.
+        data.color.store(BLACK, Ordering::Relaxed);
+        Gc {
+            ptr: self.ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Trace + Send + Sync + 'static> Drop for Gc<T> {
+    fn drop(&mut self) {
+        let _guard = MutatorGuard::new();
+        unsafe { decrement(self.node()) };
+    }
+}
+
+/// Releases one count of `node`.
+///
+/// # Safety
+///
+/// The caller must own one count of `node` and hold a `MutatorGuard`.
+unsafe fn decrement(node: Node) {
+    let data = node.0.as_ref();
+    // The value is being collected, and so are the `Gc`s it owns.
+    if data.dropped.load(Ordering::Relaxed) {
+        return;
+    }
+    // If there will be other `Gc`s left, buffer the node as a possible root
+    // now, while our count still keeps it alive. Whoever drops the count to
+    // zero will then see it's buffered and leave freeing it to the collector.
+    if data.count.load(Ordering::Relaxed) > 1 {
+        data.color.store(PURPLE, Ordering::Relaxed);
+        if !data.buffered.swap(true, Ordering::Relaxed) {
+            ROOTS.lock().push(node);
+        }
+    }
+    if data.count.fetch_sub(1, Ordering::Release) == 1 {
+        fence(Ordering::Acquire);
+        release(node);
+    }
+}
+
+/// Drops the value of `node` once its count dropped to zero.
+unsafe fn release(node: Node) {
+    let data = node.0.as_ref();
+    data.color.store(BLACK, Ordering::Relaxed);
+    data.dropped.store(true, Ordering::Relaxed);
+    ManuallyDrop::drop(&mut *data.value.get());
+    if !data.buffered.load(Ordering::Relaxed) {
+        drop(Box::from_raw(node.0.as_ptr()));
+    }
+}
+
+impl Node {
+    fn data(&self) -> &GcBox<dyn Trace + Send + Sync> {
+        unsafe { self.0.as_ref() }
+    }
+
+    fn color(&self) -> u8 {
+        self.data().color.load(Ordering::Relaxed)
+    }
+
+    fn set_color(&self, color: u8) {
+        self.data().color.store(color, Ordering::Relaxed)
+    }
+
+    /// Calls `f` for every child of this node.
+    ///
+    /// Must only be called by the collector, on nodes whose value is alive.
+    fn for_each_child(&self, mut f: impl FnMut(Node)) {
+        let value = unsafe { &*self.data().value.get() };
+        value.trace(&mut Tracer { visit: &mut f });
+    }
+}
+
+/// Runs a cycle collection, returning the number of values freed.
+///
+/// Waits until no `GcRef` is alive on any thread. Returns 0 right away
+/// if called from a `Drop` that runs while this thread is using a `Gc`,
+/// e.g. dropping the last `Gc` of a value, since the collector would wait
+/// for this thread forever.
+///
+/// # Panics
+///
+/// Panics if called while holding a `GcRef`, which would never return.
+pub fn collect_cycles() -> usize {
+    assert_eq!(
+        BORROWS.get(),
+        0,
+        "collect_cycles called while holding a GcRef"
+    );
+    if COLLECTING.get() || MUTATING.get() > 0 {
+        // Called from a `Drop` during collection, or during `Gc::drop`.
+        return 0;
+    }
+    let _collector = loop {
+        if let Some(guard) = COLLECTOR.try_write() {
+            break guard;
+        }
+        thread::yield_now();
+    };
+
+    /// Resets `COLLECTING` even if a `Drop` panics.
+    struct Collecting;
+    impl Drop for Collecting {
+        fn drop(&mut self) {
+            COLLECTING.set(false);
+        }
+    }
+    COLLECTING.set(true);
+    let _collecting = Collecting;
+
+    unsafe { collect() }
+}
+
+/// The synchronous cycle collection algorithm.
+///
+/// # Safety
+///
+/// Must be called with the `COLLECTOR` write locked.
+unsafe fn collect() -> usize {
+    let roots = std::mem::take(&mut *ROOTS.lock());
+
+    // Mark roots: subtract the counts from internal references,
+    // starting from every possible root.
+    let mut candidates = Vec::with_capacity(roots.len());
+    for node in roots {
+        let data = node.data();
+        if data.color.load(Ordering::Relaxed) == PURPLE && !data.dropped.load(Ordering::Relaxed) {
+            mark_gray(node);
+            candidates.push(node);
+        } else {
+            data.buffered.store(false, Ordering::Relaxed);
+            if data.dropped.load(Ordering::Relaxed) {
+                // Its count dropped to zero while buffered.
+                drop(Box::from_raw(node.0.as_ptr()));
+            }
+        }
+    }
+
+    // Scan roots: anything still referenced from outside is alive,
+    // and so is everything it references.
+    for node in &candidates {
+        scan(*node);
+    }
+
+    // Collect roots: what's left is only referenced from within the cycles.
+    let mut garbage = Vec::new();
+    for node in candidates {
+        node.data().buffered.store(false, Ordering::Relaxed);
+        collect_white(node, &mut garbage);
+    }
+
+    // Mark the garbage as dropped first, so dropping the values won't
+    // touch the counts of other nodes in the garbage.
+    for node in &garbage {
+        node.data().dropped.store(true, Ordering::Relaxed);
+    }
+    // `mark_gray` took the counts of edges to live nodes too, but dropping
+    // the values releases them again, so give them back first.
+    for node in &garbage {
+        node.for_each_child(|child| {
+            if !child.data().dropped.load(Ordering::Relaxed) {
+                child.data().count.fetch_add(1, Ordering::Relaxed);
+            }
+        });
+    }
+    for node in &garbage {
+        ManuallyDrop::drop(&mut *node.data().value.get());
+    }
+    for node in &garbage {
+        drop(Box::from_raw(node.0.as_ptr()));
+    }
+    garbage.len()
+}
+
+fn mark_gray(root: Node) {
+    if root.color() == GRAY {
+        return;
+    }
+    root.set_color(GRAY);
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        node.for_each_child(|child| {
+            child.data().count.fetch_sub(1, Ordering::Relaxed);
+            if child.color() != GRAY {
+                child.set_color(GRAY);
+                stack.push(child);
+            }
+        });
+    }
+}
+
+fn scan(root: Node) {
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if node.color() != GRAY {
+            continue;
+        }
+        if node.data().count.load(Ordering::Relaxed) > 0 {
+            scan_black(node);
+        } else {
+            node.set_color(WHITE);
+            node.for_each_child(|child| stack.push(child));
+        }
+    }
+}
+
+fn scan_black(root: Node) {
+    root.set_color(BLACK);
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        node.for_each_child(|child| {
+            child.data().count.fetch_add(1, Ordering::Relaxed);
+            if child.color() != BLACK {
+                child.set_color(BLACK);
+                stack.push(child);
+            }
+        });
+    }
+}
+
+fn collect_white(root: Node, garbage: &mut Vec<Node>) {
+    let mut stack = VecDeque::from([root]);
+    while let Some(node) = stack.pop_front() {
+        let data = node.data();
+        if node.color() == WHITE && !data.buffered.load(Ordering::Relaxed) {
+            node.set_color(BLACK);
+            garbage.push(node);
+            node.for_each_child(|child| stack.push_back(child));
+        }
+    }
+}
+
+/// A background thread running `collect_cycles` periodically.
+///
+/// The thread is stopped when this is dropped.
+pub struct Collector {
+    stop: std::sync::Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+/// Spawns a background thread that collects cycles every `interval`.
+pub fn spawn_collector(interval: Duration) -> Collector {
+    let stop = std::sync::Arc::new(AtomicBool::new(false));
+    let handle = thread::spawn({
+        let stop = stop.clone();
+        move || {
+            while !stop.load(Ordering::Acquire) {
+                collect_cycles();
+                thread::park_timeout(interval);
+            }
+        }
+    });
+    Collector {
+        stop,
+        handle: Some(handle),
+    }
+}
+
+impl Drop for Collector {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(handle) = self.handle.take() {
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+    }
+}
+
+impl fmt::Debug for Collector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Collector").finish_non_exhaustive()
+    }
+}
+
+impl<T: Trace + Send + Sync + 'static> Deref for GcRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: Trace + Send + Sync + 'static> Drop for GcRef<'_, T> {
+    fn drop(&mut self) {
+        BORROWS.set(BORROWS.get() - 1);
+    }
+}
+
+impl<T: Trace + Send + Sync + 'static + fmt::Debug> fmt::Debug for Gc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.borrow(), f)
+    }
+}
+
+impl<T: Trace + Send + Sync + 'static + fmt::Debug> fmt::Debug for GcRef<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+unsafe impl<T: Trace + Send + Sync + 'static> Trace for Gc<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        tracer.visit(self);
+    }
+}
+
+unsafe impl<T: Trace + ?Sized> Trace for crate::Mutex<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        // It can only be locked if the guard was leaked,
+        // skipping it just keeps its children alive.
+        if let Some(value) = self.try_lock() {
+            value.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: Trace + ?Sized> Trace for crate::RwLock<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        if let Some(value) = self.try_read() {
+            value.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: Trace + ?Sized> Trace for crate::SpinLock<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        if let Some(value) = self.try_lock() {
+            value.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: Trace> Trace for Option<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        if let Some(value) = self {
+            value.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: Trace + ?Sized> Trace for Box<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        (**self).trace(tracer);
+    }
+}
+
+unsafe impl<T: Trace> Trace for [T] {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        for value in self {
+            value.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: Trace, const N: usize> Trace for [T; N] {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        self[..].trace(tracer);
+    }
+}
+
+unsafe impl<T: Trace> Trace for Vec<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        self[..].trace(tracer);
+    }
+}
+
+unsafe impl<T: Trace> Trace for VecDeque<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        for value in self {
+            value.trace(tracer);
+        }
+    }
+}
+
+/// Implements `Trace` for types that can't own a `Gc`.
+macro_rules! impl_trace_for_leaf {
+    ($($t:ty),* $(,)?) => {
+        $(
+            unsafe impl Trace for $t {
+                fn trace(&self, _: &mut Tracer<'_>) {}
+            }
+        )*
+    };
+}
+
+impl_trace_for_leaf!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    str,
+    String,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Node {
+        id: usize,
+        edges: Mutex<Vec<Gc<Node>>>,
+        drops: &'static AtomicUsize,
+    }
+
+    unsafe impl Trace for Node {
+        fn trace(&self, tracer: &mut Tracer<'_>) {
+            self.edges.trace(tracer);
+        }
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            self.drops.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn node(id: usize, drops: &'static AtomicUsize) -> Gc<Node> {
+        Gc::new(Node {
+            id,
+            edges: Mutex::new(Vec::new()),
+            drops,
+        })
+    }
+
+    fn link(from: &Gc<Node>, to: &Gc<Node>) {
+        from.borrow().edges.lock().push(to.clone());
+    }
+
+    #[test]
+    fn acyclic_values_should_be_freed_immediately() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        let a = node(0, &DROPS);
+        let b = node(1, &DROPS);
+        link(&a, &b);
+        drop(b);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+        drop(a);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn cycle_should_be_collected() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        // root -> child1 -> root, root -> child2 -> root, child2 -> self
+        let root = node(0, &DROPS);
+        let child1 = node(1, &DROPS);
+        let child2 = node(2, &DROPS);
+        link(&root, &child1);
+        link(&child1, &root);
+        link(&root, &child2);
+        link(&child2, &root);
+        link(&child2, &child2);
+        drop((child1, child2));
+
+        // Still referenced from outside.
+        collect_cycles();
+        assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+        assert_eq!(root.borrow().edges.lock().len(), 2);
+        assert_eq!(Gc::strong_count(&root), 3);
+
+        // The background collector of another test might free it
+        // before we do, so don't check that nothing was dropped yet.
+        drop(root);
+        collect_cycles();
+        assert_eq!(DROPS.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn cycle_reachable_from_live_value_should_not_be_collected() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        let owner = node(0, &DROPS);
+        let a = node(1, &DROPS);
+        let b = node(2, &DROPS);
+        link(&a, &b);
+        link(&b, &a);
+        link(&owner, &a);
+        drop((a, b));
+
+        collect_cycles();
+        assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+        let a = owner.borrow().edges.lock()[0].clone();
+        assert_eq!(a.borrow().id, 1);
+        let b = a.borrow().edges.lock()[0].clone();
+        assert_eq!(b.borrow().id, 2);
+        drop((a, b));
+
+        drop(owner);
+        collect_cycles();
+        assert_eq!(DROPS.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn collected_cycle_should_release_live_values() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        let live = node(0, &DROPS);
+        let a = node(1, &DROPS);
+        let b = node(2, &DROPS);
+        link(&a, &b);
+        link(&b, &a);
+        link(&a, &live);
+        link(&b, &live);
+        drop((a, b));
+
+        collect_cycles();
+        assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+        assert_eq!(Gc::strong_count(&live), 1);
+        assert_eq!(live.borrow().id, 0);
+
+        drop(live);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn background_collector_should_collect_concurrent_cycles() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        let collector = spawn_collector(Duration::from_millis(1));
+
+        thread::scope(|s| {
+            for t in 0..4 {
+                s.spawn(move || {
+                    for i in 0..200 {
+                        // A ring of three nodes, mutated while the
+                        // collector may be running.
+                        let nodes: Vec<_> =
+                            (0..3).map(|j| node(t * 1000 + i * 3 + j, &DROPS)).collect();
+                        for j in 0..3 {
+                            link(&nodes[j], &nodes[(j + 1) % 3]);
+                        }
+                        let first = nodes[0].borrow().edges.lock()[0].clone();
+                        assert_eq!(first.borrow().id, nodes[1].borrow().id);
+                    }
+                });
+            }
+        });
+
+        drop(collector);
+        collect_cycles();
+        assert_eq!(DROPS.load(Ordering::Relaxed), 4 * 200 * 3);
+    }
+
+    #[test]
+    fn collect_from_drop_of_gc_value_should_return() {
+        struct CollectOnDrop;
+        unsafe impl Trace for CollectOnDrop {
+            fn trace(&self, _: &mut Tracer<'_>) {}
+        }
+        impl Drop for CollectOnDrop {
+            fn drop(&mut self) {
+                // Runs inside `Gc::drop`, which keeps the collector out.
+                assert_eq!(collect_cycles(), 0);
+            }
+        }
+
+        drop(Gc::new(CollectOnDrop));
+        // The guard is released again afterwards.
+        collect_cycles();
+    }
+
+    #[test]
+    #[should_panic(expected = "holding a GcRef")]
+    fn collect_while_borrowed_should_panic() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        let a = node(0, &DROPS);
+        let _r = a.borrow();
+        collect_cycles();
+    }
+}
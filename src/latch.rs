@@ -0,0 +1,211 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+use atomic_wait::{wait, wake_all};
+
+use crate::{futex, Arc};
+
+/// Lets threads wait until a counter has been counted down to zero.
+pub struct CountDownLatch {
+    count: AtomicU32,
+}
+
+/// Waits until all clones of the `WaitGroup` have been dropped.
+///
+/// ```
+/// use conutils::WaitGroup;
+///
+/// let wg = WaitGroup::new();
+/// for _ in 0..4 {
+///     let wg = wg.clone();
+///     std::thread::spawn(move || {
+///         // Do some work, then let the others know.
+///         drop(wg);
+///     });
+/// }
+/// wg.wait();
+/// ```
+pub struct WaitGroup {
+    latch: Arc<CountDownLatch>,
+}
+
+impl CountDownLatch {
+    pub const fn new(count: u32) -> Self {
+        Self {
+            count: AtomicU32::new(count),
+        }
+    }
+
+    /// Decrements the count, releasing the waiting threads when it reaches zero.
+    ///
+    /// Does nothing if the count is already zero.
+    pub fn count_down(&self) {
+        // Release so the waiters see everything done before counting down,
+        // Acquire so the last one does too, as it wakes them.
+        if let Ok(1) = self
+            .count
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |n| n.checked_sub(1))
+        {
+            wake_all(&self.count);
+        }
+    }
+
+    /// Gets the current count.
+    pub fn count(&self) -> u32 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Blocks until the count reaches zero.
+    pub fn wait(&self) {
+        loop {
+            let n = self.count.load(Ordering::Acquire);
+            if n == 0 {
+                return;
+            }
+            wait(&self.count, n);
+        }
+    }
+
+    /// Blocks until the count reaches zero, giving up after `timeout`.
+    ///
+    /// Returns `true` if the count reached zero. A timeout too large to
+    /// represent waits forever.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            self.wait();
+            return true;
+        };
+        loop {
+            let n = self.count.load(Ordering::Acquire);
+            if n == 0 {
+                return true;
+            }
+            if !futex::wait_until(&self.count, n, deadline) {
+                return self.count.load(Ordering::Acquire) == 0;
+            }
+        }
+    }
+}
+
+impl WaitGroup {
+    pub fn new() -> Self {
+        Self {
+            latch: Arc::new(CountDownLatch::new(1)),
+        }
+    }
+
+    /// Drops this handle and waits until all other clones are dropped.
+    pub fn wait(self) {
+        let latch = self.latch.clone();
+        drop(self);
+        latch.wait();
+    }
+}
+
+impl Clone for WaitGroup {
+    fn clone(&self) -> Self {
+        // The count can't be zero while we exist, so no need to wake anyone.
+        if self.latch.count.fetch_add(1, Ordering::Relaxed) == u32::MAX {
+            std::process::abort();
+        }
+        Self {
+            latch: self.latch.clone(),
+        }
+    }
+}
+
+impl Drop for WaitGroup {
+    fn drop(&mut self) {
+        self.latch.count_down();
+    }
+}
+
+impl Default for WaitGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for CountDownLatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CountDownLatch")
+            .field("count", &self.count())
+            .finish()
+    }
+}
+
+impl fmt::Debug for WaitGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WaitGroup")
+            .field("count", &self.latch.count())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicUsize, thread};
+
+    use super::*;
+
+    #[test]
+    fn count_down_latch_should_work() {
+        let latch = CountDownLatch::new(3);
+        let done = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    done.fetch_add(1, Ordering::Relaxed);
+                    latch.count_down();
+                });
+            }
+            latch.wait();
+            assert_eq!(done.load(Ordering::Relaxed), 3);
+        });
+        // Counting down past zero does nothing.
+        latch.count_down();
+        assert_eq!(latch.count(), 0);
+    }
+
+    #[test]
+    fn wait_timeout_should_work() {
+        let latch = CountDownLatch::new(1);
+        assert!(!latch.wait_timeout(Duration::from_millis(10)));
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                latch.count_down();
+            });
+            assert!(latch.wait_timeout(Duration::from_secs(10)));
+        });
+
+        let latch = CountDownLatch::new(1);
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                latch.count_down();
+            });
+            assert!(latch.wait_timeout(Duration::MAX));
+        });
+    }
+
+    #[test]
+    fn wait_group_should_wait_for_all_clones() {
+        let wg = WaitGroup::new();
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..4 {
+            let wg = wg.clone();
+            let done = done.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(5));
+                done.fetch_add(1, Ordering::Relaxed);
+                drop(wg);
+            });
+        }
+        wg.wait();
+        assert_eq!(done.load(Ordering::Relaxed), 4);
+    }
+}
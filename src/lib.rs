@@ -1,15 +1,59 @@
 mod arc;
+mod array_queue;
+mod atomic_arc;
+mod backoff;
+mod barrier;
+mod biased;
+mod bravo;
+mod cache_padded;
+mod clhlock;
 mod condvar;
+mod deque;
+mod epoch;
+mod event;
+mod futex;
+mod gc;
+mod latch;
+mod mcslock;
 mod mpsc;
+mod ms_queue;
 mod mutex;
+mod once;
 mod oneshot;
+mod parker;
 mod rwlock;
+mod semaphore;
+mod seqlock;
 mod spinlock;
+mod spsc;
+mod strong_arc;
+mod ticketlock;
+mod treiber_stack;
 
 pub use arc::*;
+pub use array_queue::*;
+pub use atomic_arc::*;
+pub use barrier::*;
+pub use biased::*;
+pub use bravo::*;
+pub use clhlock::*;
 pub use condvar::*;
+pub use deque::*;
+pub use event::*;
+pub use gc::{collect_cycles, spawn_collector, Collector, Gc, GcRef, Trace, Tracer};
+pub use latch::*;
+pub use mcslock::*;
 pub use mpsc::{unbounded, Receiver as MPSCReceiver, Sender as MPSCSender};
+pub use ms_queue::*;
 pub use mutex::*;
+pub use once::*;
 pub use oneshot::{Channel, Receiver as OneShotReceiver, Sender as OneShotSender};
+pub use parker::*;
 pub use rwlock::*;
+pub use semaphore::*;
+pub use seqlock::*;
 pub use spinlock::*;
+pub use spsc::{channel as spsc_channel, Receiver as SPSCReceiver, Sender as SPSCSender};
+pub use strong_arc::*;
+pub use ticketlock::*;
+pub use treiber_stack::*;
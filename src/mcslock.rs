@@ -0,0 +1,265 @@
+use std::{
+    cell::{RefCell, UnsafeCell},
+    fmt,
+    ops::{Deref, DerefMut},
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+
+use crate::backoff::Backoff;
+
+/// A fair queue lock where every waiter spins on its own node.
+///
+/// Waiters form a linked list, and the lock holder hands the lock to the
+/// next one by clearing the flag in its node, so no two waiters spin on
+/// the same cache line.
+pub struct McsLock<T: ?Sized> {
+    /// The last node in the queue, or null if unlocked.
+    tail: AtomicPtr<McsNode>,
+    value: UnsafeCell<T>,
+}
+
+struct McsNode {
+    /// Set until the previous lock holder hands us the lock.
+    locked: AtomicBool,
+    next: AtomicPtr<McsNode>,
+}
+
+pub struct McsLockGuard<'a, T: ?Sized> {
+    lock: &'a McsLock<T>,
+    node: *mut McsNode,
+}
+
+unsafe impl<T: ?Sized> Sync for McsLock<T> where T: Send {}
+
+thread_local! {
+    /// Nodes of released locks, reused so that locking doesn't allocate.
+    // Boxed, since other threads keep pointers to queued nodes.
+    #[allow(clippy::vec_box)]
+    static FREE_NODES: RefCell<Vec<Box<McsNode>>> = const { RefCell::new(Vec::new()) };
+}
+
+impl McsNode {
+    /// Takes a node from this thread's free list, or allocates one.
+    fn acquire() -> *mut McsNode {
+        let node = FREE_NODES
+            .try_with(|nodes| nodes.borrow_mut().pop())
+            .ok()
+            .flatten();
+        match node {
+            Some(node) => {
+                node.locked.store(true, Ordering::Relaxed);
+                node.next.store(ptr::null_mut(), Ordering::Relaxed);
+                Box::into_raw(node)
+            }
+            None => Box::into_raw(Box::new(McsNode {
+                locked: AtomicBool::new(true),
+                next: AtomicPtr::new(ptr::null_mut()),
+            })),
+        }
+    }
+
+    /// Puts a node nobody else references anymore back on the free list.
+    unsafe fn release(node: *mut McsNode) {
+        let node = Box::from_raw(node);
+        // Just frees the node if the thread is exiting.
+        let _ = FREE_NODES.try_with(|nodes| nodes.borrow_mut().push(node));
+    }
+}
+
+impl<T> McsLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            tail: AtomicPtr::new(ptr::null_mut()),
+            value: UnsafeCell::new(value),
+        }
+    }
+}
+
+impl<T: ?Sized> McsLock<T> {
+    pub fn lock(&self) -> McsLockGuard<'_, T> {
+        let node = McsNode::acquire();
+        // Acquire matches the Release in `drop` if the lock was free,
+        // Release publishes our node to the next waiter.
+        let prev = self.tail.swap(node, Ordering::AcqRel);
+        if !prev.is_null() {
+            // Safety: `prev` is only freed after it handed us the lock,
+            // which needs this link first.
+            unsafe {
+                (*prev).next.store(node, Ordering::Release);
+                let mut backoff = Backoff::new();
+                // Acquire matches the Release store in `drop`.
+                while (*node).locked.load(Ordering::Acquire) {
+                    backoff.snooze();
+                }
+            }
+        }
+        McsLockGuard { lock: self, node }
+    }
+
+    /// Tries to acquire the lock without spinning.
+    pub fn try_lock(&self) -> Option<McsLockGuard<'_, T>> {
+        if !self.tail.load(Ordering::Relaxed).is_null() {
+            return None;
+        }
+        let node = McsNode::acquire();
+        match self.tail.compare_exchange(
+            ptr::null_mut(),
+            node,
+            Ordering::Acquire,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => Some(McsLockGuard { lock: self, node }),
+            Err(_) => {
+                unsafe { McsNode::release(node) };
+                None
+            }
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for McsLockGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            let mut next = (*self.node).next.load(Ordering::Acquire);
+            if next.is_null() {
+                // Nobody is queued behind us, try to mark the lock as free.
+                if self
+                    .lock
+                    .tail
+                    .compare_exchange(
+                        self.node,
+                        ptr::null_mut(),
+                        Ordering::Release,
+                        Ordering::Relaxed,
+                    )
+                    .is_ok()
+                {
+                    McsNode::release(self.node);
+                    return;
+                }
+                // Someone swapped the tail but didn't link their node yet.
+                let mut backoff = Backoff::new();
+                loop {
+                    next = (*self.node).next.load(Ordering::Acquire);
+                    if !next.is_null() {
+                        break;
+                    }
+                    backoff.snooze();
+                }
+            }
+            // Nobody touches our node anymore once the next one is linked.
+            McsNode::release(self.node);
+            (*next).locked.store(false, Ordering::Release);
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for McsLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: The guard means we hold the lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for McsLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for McsLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("McsLock");
+        // Never spin in `Debug`, the lock might be held by the caller.
+        match self.try_lock() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+impl<T: Default> Default for McsLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for McsLock<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for McsLockGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for McsLockGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn multi_threads_should_work() {
+        let lock = McsLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *lock.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*lock.lock(), 4000);
+    }
+
+    #[test]
+    fn try_lock_and_debug_should_work() {
+        let lock = McsLock::new(1);
+        assert_eq!(format!("{lock:?}"), "McsLock { data: 1, .. }");
+        let guard = lock.try_lock().unwrap();
+        assert!(lock.try_lock().is_none());
+        assert_eq!(format!("{lock:?}"), "McsLock { data: <locked>, .. }");
+        assert_eq!(guard.to_string(), "1");
+    }
+
+    #[test]
+    fn nested_locks_should_reuse_nodes() {
+        let (a, b) = (McsLock::new(1), McsLock::new(2));
+        let first = {
+            let ga = a.lock();
+            let gb = b.lock();
+            assert_eq!(*ga + *gb, 3);
+            ga.node
+        };
+        // The nodes went back to this thread's free list.
+        let ga = a.lock();
+        let gb = b.lock();
+        assert!(ga.node == first || gb.node == first);
+    }
+
+    #[test]
+    fn unsized_value_should_work() {
+        let lock: &McsLock<[u8]> = &McsLock::new([0; 4]);
+        thread::scope(|s| {
+            for i in 0..4 {
+                s.spawn(move || lock.lock()[i] = i as u8);
+            }
+        });
+        assert_eq!(&*lock.lock(), &[0, 1, 2, 3]);
+    }
+}
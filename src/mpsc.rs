@@ -1,22 +1,62 @@
 use anyhow::Result;
+use atomic_wait::{wait, wake_one};
 use std::{
-    collections::VecDeque,
-    sync::atomic::Ordering,
-    sync::{atomic::AtomicUsize, Arc, Condvar, Mutex},
+    cell::UnsafeCell,
+    fmt,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicUsize, Ordering},
+    sync::Arc,
 };
 
+use crate::{backoff::Backoff, cache_padded::CachePadded};
+
+/// Number of messages in a block.
+const BLOCK_CAP: usize = 31;
+/// Positions per block. The extra one marks the tail while
+/// the sender that filled the block installs the next one.
+const LAP: usize = BLOCK_CAP + 1;
+
+/// A slot for one message.
+struct Slot<T> {
+    value: UnsafeCell<MaybeUninit<T>>,
+    /// Set once the message is written.
+    ready: AtomicBool,
+}
+
+/// A segment of the queue, linked to the next one.
+struct Block<T> {
+    next: AtomicPtr<Block<T>>,
+    slots: [Slot<T>; BLOCK_CAP],
+}
+
 /// Shared state between the sender and the receiver.
+///
+/// The messages live in a linked list of blocks. Senders claim a slot by
+/// bumping the tail position, and the receiver consumes the slots in order
+/// and frees each block once it's done with it.
 struct Shared<T> {
-    /// The queue of messages.
-    queue: Mutex<VecDeque<T>>,
-    /// The condition variable to notify the receiver when there is a new message.
-    available: Condvar,
+    /// The position of the next message to send.
+    tail: CachePadded<AtomicUsize>,
+    /// The block of `tail`.
+    tail_block: AtomicPtr<Block<T>>,
+    /// The position of the next message to receive, published by the receiver
+    /// after every message so that `total_queued_items` is exact. Only read
+    /// there, so the Relaxed store stays in the receiver's cache line.
+    head: CachePadded<AtomicUsize>,
+    /// The block of `head`, published by the receiver.
+    head_block: AtomicPtr<Block<T>>,
+    /// Set to 1 while the receiver is (about to go) asleep.
+    sleeping: AtomicU32,
     /// The number of senders.
     senders: AtomicUsize,
     /// The number of receivers.
     receivers: AtomicUsize,
 }
 
+unsafe impl<T: Send> Send for Shared<T> {}
+unsafe impl<T: Send> Sync for Shared<T> {}
+
 /// The sender of the channel.
 pub struct Sender<T> {
     shared: Arc<Shared<T>>,
@@ -25,7 +65,32 @@ pub struct Sender<T> {
 /// The receiver of the channel.
 pub struct Receiver<T> {
     shared: Arc<Shared<T>>,
-    cached: VecDeque<T>,
+    /// The position of the next message to receive.
+    head: usize,
+    /// The block of `head`. Only the receiver moves past it, so it's ours.
+    block: *mut Block<T>,
+}
+
+unsafe impl<T: Send> Send for Receiver<T> {}
+// Safety: `block` is only used through `&mut self`, a shared `Receiver`
+// only gives access to the counts in `Shared`.
+unsafe impl<T: Send> Sync for Receiver<T> {}
+
+impl<T> Block<T> {
+    fn new() -> *mut Block<T> {
+        Box::into_raw(Box::new(Block {
+            next: AtomicPtr::new(ptr::null_mut()),
+            slots: std::array::from_fn(|_| Slot {
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+                ready: AtomicBool::new(false),
+            }),
+        }))
+    }
+}
+
+/// Converts a position into the number of messages before it.
+fn position_to_count(pos: usize) -> usize {
+    pos / LAP * BLOCK_CAP + (pos % LAP).min(BLOCK_CAP)
 }
 
 impl<T> Sender<T> {
@@ -34,15 +99,59 @@ impl<T> Sender<T> {
             return Err(anyhow::anyhow!("no receiver"));
         }
 
-        let was_empty = {
-            let mut inner = self.shared.queue.lock().unwrap();
-            let empty = inner.is_empty();
-            inner.push_back(item);
-            empty
+        let shared = &*self.shared;
+        let mut backoff = Backoff::new();
+        let mut tail = shared.tail.load(Ordering::Acquire);
+        let mut next_block = None;
+        let (block, offset) = loop {
+            let offset = tail % LAP;
+            if offset == BLOCK_CAP {
+                // Another sender is installing the next block.
+                backoff.snooze();
+                tail = shared.tail.load(Ordering::Acquire);
+                continue;
+            }
+            // Allocate the next block before claiming the last slot,
+            // so the others don't have to wait for the allocation.
+            if offset + 1 == BLOCK_CAP && next_block.is_none() {
+                next_block = Some(Block::new());
+            }
+            // Acquire on `tail` makes sure this is the block of `tail`.
+            let block = shared.tail_block.load(Ordering::Acquire);
+            match shared.tail.compare_exchange_weak(
+                tail,
+                tail + 1,
+                Ordering::SeqCst,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break (block, offset),
+                Err(t) => tail = t,
+            }
         };
 
-        if was_empty {
-            self.shared.available.notify_one();
+        unsafe {
+            if offset + 1 == BLOCK_CAP {
+                // We took the last slot, move the tail to the next block.
+                let next = next_block.take().unwrap();
+                shared.tail_block.store(next, Ordering::Release);
+                shared.tail.fetch_add(1, Ordering::Release);
+                (*block).next.store(next, Ordering::Release);
+            }
+            let slot = &(*block).slots[offset];
+            (*slot.value.get()).write(item);
+            // SeqCst pairs with `recv`: either the receiver sees the
+            // message, or we see that it's going to sleep.
+            slot.ready.store(true, Ordering::SeqCst);
+        }
+        if let Some(block) = next_block {
+            // Someone else installed the block we allocated.
+            drop(unsafe { Box::from_raw(block) });
+        }
+
+        if shared.sleeping.load(Ordering::SeqCst) == 1
+            && shared.sleeping.swap(0, Ordering::Relaxed) == 1
+        {
+            wake_one(&shared.sleeping);
         }
 
         Ok(())
@@ -53,45 +162,75 @@ impl<T> Sender<T> {
     }
 
     pub fn total_queued_items(&self) -> usize {
-        let inner = self.shared.queue.lock().unwrap();
-        inner.len()
+        let head = self.shared.head.load(Ordering::Relaxed);
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        position_to_count(tail).saturating_sub(position_to_count(head))
     }
 }
 
 impl<T> Receiver<T> {
     pub fn recv(&mut self) -> Result<T> {
-        // fast path
-        if let Some(t) = self.cached.pop_front() {
-            return Ok(t);
-        }
-
-        let mut inner = self.shared.queue.lock().unwrap();
         loop {
-            match inner.pop_front() {
-                Some(t) => {
-                    // if there is still message in the queue, swap the cached and the queue.
-                    if !inner.is_empty() {
-                        std::mem::swap(&mut self.cached, &mut inner);
-                    }
-                    return Ok(t);
-                }
-                None if self.total_senders() == 0 => return Err(anyhow::anyhow!("no sender")),
-                None => {
-                    inner = self
-                        .shared
-                        .available
-                        // Wait for the sender to send a message,
-                        // here it would release the MutexGuard(inner) and wait for notification from Condvar.
-                        .wait(inner)
-                        .map_err(|_| anyhow::anyhow!("lock poisoned"))?;
-                }
+            // fast path
+            if let Some(t) = self.try_pop() {
+                return Ok(t);
+            }
+
+            let shared = &*self.shared;
+            // Pairs with the SeqCst store of `ready` in `send`.
+            shared.sleeping.store(1, Ordering::SeqCst);
+            if self.is_ready() {
+                shared.sleeping.store(0, Ordering::Relaxed);
+                continue;
+            }
+            if self.total_senders() == 0 {
+                shared.sleeping.store(0, Ordering::Relaxed);
+                // A message sent right before the last sender left.
+                return self.try_pop().ok_or_else(|| anyhow::anyhow!("no sender"));
             }
+            // Returns right away if a sender already cleared the flag.
+            wait(&shared.sleeping, 1);
+            shared.sleeping.store(0, Ordering::Relaxed);
         }
     }
 
     pub fn total_senders(&self) -> usize {
         self.shared.senders.load(Ordering::SeqCst)
     }
+
+    fn is_ready(&self) -> bool {
+        let offset = self.head % LAP;
+        unsafe { (*self.block).slots[offset].ready.load(Ordering::SeqCst) }
+    }
+
+    fn try_pop(&mut self) -> Option<T> {
+        let offset = self.head % LAP;
+        // Safety: We never move past a slot before reading it, so senders
+        // are done with every block before we free it.
+        unsafe {
+            let slot = &(*self.block).slots[offset];
+            // Acquire matches the store of `ready` in `send`.
+            if !slot.ready.load(Ordering::Acquire) {
+                return None;
+            }
+            let value = (*slot.value.get()).assume_init_read();
+
+            if offset + 1 == BLOCK_CAP {
+                // The sender of the last slot linked the next block before
+                // marking its slot ready.
+                let next = (*self.block).next.load(Ordering::Acquire);
+                drop(Box::from_raw(self.block));
+                self.block = next;
+                self.shared.head_block.store(next, Ordering::Relaxed);
+                // Skip the marker position.
+                self.head += 2;
+            } else {
+                self.head += 1;
+            }
+            self.shared.head.store(self.head, Ordering::Relaxed);
+            Some(value)
+        }
+    }
 }
 
 impl<T> Iterator for Receiver<T> {
@@ -113,12 +252,12 @@ impl<T> Clone for Sender<T> {
 
 impl<T> Drop for Sender<T> {
     fn drop(&mut self) {
-        let old = self.shared.senders.fetch_sub(1, Ordering::AcqRel);
+        let old = self.shared.senders.fetch_sub(1, Ordering::SeqCst);
 
         // If all senders are dropped, notify the receiver to read the remaining messages.
         // If there is no available message, the receiver would get an error.
-        if old <= 1 {
-            self.shared.available.notify_all();
+        if old <= 1 && self.shared.sleeping.swap(0, Ordering::SeqCst) == 1 {
+            wake_one(&self.shared.sleeping);
         }
     }
 }
@@ -129,9 +268,45 @@ impl<T> Drop for Receiver<T> {
     }
 }
 
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        // Everyone is gone, so every claimed slot has been written.
+        let tail = *self.tail.get_mut();
+        let mut head = *self.head.get_mut();
+        let mut block = *self.head_block.get_mut();
+        unsafe {
+            while head != tail {
+                let offset = head % LAP;
+                if offset < BLOCK_CAP {
+                    (*(*block).slots[offset].value.get()).assume_init_drop();
+                } else {
+                    let next = *(*block).next.get_mut();
+                    drop(Box::from_raw(block));
+                    block = next;
+                }
+                head += 1;
+            }
+            drop(Box::from_raw(block));
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
 /// Create a new unbounded channel.
 pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
     let shared = Shared::default();
+    let block = shared.head_block.load(Ordering::Relaxed);
     let shared = Arc::new(shared);
     (
         Sender {
@@ -139,17 +314,21 @@ pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
         },
         Receiver {
             shared,
-            cached: VecDeque::with_capacity(INITIAL_SIZE),
+            head: 0,
+            block,
         },
     )
 }
 
-const INITIAL_SIZE: usize = 32;
 impl<T> Default for Shared<T> {
     fn default() -> Self {
+        let block = Block::new();
         Self {
-            queue: Mutex::new(VecDeque::with_capacity(INITIAL_SIZE)),
-            available: Condvar::new(),
+            tail: CachePadded::new(AtomicUsize::new(0)),
+            tail_block: AtomicPtr::new(block),
+            head: CachePadded::new(AtomicUsize::new(0)),
+            head_block: AtomicPtr::new(block),
+            sleeping: AtomicU32::new(0),
             senders: AtomicUsize::new(1),
             receivers: AtomicUsize::new(1),
         }
@@ -162,6 +341,13 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn sender_and_receiver_should_be_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Sender<String>>();
+        assert_send_sync::<Receiver<String>>();
+    }
+
     #[test]
     fn channel_should_work() {
         let (s, mut r) = unbounded();
@@ -291,16 +477,49 @@ mod tests {
     #[test]
     fn channel_fast_path_should_work() {
         let (s, mut r) = unbounded::<usize>();
-        for i in 0..10 {
+        // Enough messages to fill a few blocks.
+        let n = BLOCK_CAP * 3 + 5;
+        for i in 0..n {
             s.send(i).unwrap();
         }
 
-        assert!(r.cached.is_empty());
+        assert_eq!(n, s.total_queued_items());
         assert_eq!(0, r.recv().unwrap());
-        assert_eq!(9, r.cached.len());
-        assert_eq!(0, s.total_queued_items());
-        for (idx, i) in r.take(9).enumerate() {
+        assert_eq!(n - 1, s.total_queued_items());
+        for (idx, i) in r.by_ref().take(n - 1).enumerate() {
             assert_eq!(idx + 1, i);
         }
+        assert_eq!(0, s.total_queued_items());
+    }
+
+    #[test]
+    fn unreceived_messages_should_be_dropped() {
+        let value = Arc::new(());
+        let (s, mut r) = unbounded();
+        for _ in 0..BLOCK_CAP * 2 {
+            s.send(value.clone()).unwrap();
+        }
+        drop(r.recv().unwrap());
+        drop((s, r));
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn concurrent_senders_should_not_lose_messages() {
+        let (s, r) = unbounded();
+        let mut received: Vec<usize> = thread::scope(|scope| {
+            for t in 0..4 {
+                let s = s.clone();
+                scope.spawn(move || {
+                    for i in 0..1000 {
+                        s.send(t * 1000 + i).unwrap();
+                    }
+                });
+            }
+            drop(s);
+            r.collect()
+        });
+        received.sort();
+        assert_eq!(received, (0..4000).collect::<Vec<_>>());
     }
 }
@@ -0,0 +1,229 @@
+use std::{
+    fmt,
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use crate::{cache_padded::CachePadded, epoch};
+
+/// An unbounded lock-free MPMC queue, based on Michael and Scott's
+/// "Simple, Fast, and Practical Non-Blocking and Blocking Concurrent
+/// Queue Algorithms" (1996).
+///
+/// The queue is a linked list that always starts with a sentinel node.
+/// Popping makes the first real node the new sentinel, and the old one is
+/// freed through epoch-based reclamation.
+pub struct MsQueue<T> {
+    /// The sentinel node.
+    head: CachePadded<AtomicPtr<Node<T>>>,
+    /// The last node, or the one before it if a push didn't catch up yet.
+    tail: CachePadded<AtomicPtr<Node<T>>>,
+    _marker: PhantomData<T>,
+}
+
+struct Node<T> {
+    /// Uninitialized in the sentinel.
+    value: MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+unsafe impl<T: Send> Send for MsQueue<T> {}
+unsafe impl<T: Send> Sync for MsQueue<T> {}
+
+impl<T> Node<T> {
+    fn new(value: MaybeUninit<T>) -> *mut Node<T> {
+        Box::into_raw(Box::new(Node {
+            value,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+impl<T> MsQueue<T> {
+    pub fn new() -> Self {
+        let sentinel = Node::new(MaybeUninit::uninit());
+        Self {
+            head: CachePadded::new(AtomicPtr::new(sentinel)),
+            tail: CachePadded::new(AtomicPtr::new(sentinel)),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        let node = Node::new(MaybeUninit::new(value));
+        let _guard = epoch::pin();
+        loop {
+            let tail = self.tail.load(Ordering::Acquire);
+            // Safety: We're pinned, so `tail` can't have been freed.
+            let next = unsafe { &(*tail).next };
+            let next_ptr = next.load(Ordering::Acquire);
+            if !next_ptr.is_null() {
+                // The tail is lagging behind, help the other push first.
+                let _ = self.tail.compare_exchange(
+                    tail,
+                    next_ptr,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                );
+                continue;
+            }
+            // Release publishes the node and its value to `pop`.
+            if next
+                .compare_exchange(ptr::null_mut(), node, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                // Fine to fail, someone else already moved the tail for us.
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, node, Ordering::Release, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+        loop {
+            let head = self.head.load(Ordering::Acquire);
+            // Safety: We're pinned, so neither node can have been freed.
+            let next = unsafe { (*head).next.load(Ordering::Acquire) };
+            if next.is_null() {
+                return None;
+            }
+            // Don't let the tail point to a node we're about to retire.
+            let tail = self.tail.load(Ordering::Relaxed);
+            if tail == head {
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+            }
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                // Safety: `next` is the new sentinel now, so only we read its
+                // value, and the old sentinel is unreachable for new pins.
+                unsafe {
+                    let value = (*next).value.assume_init_read();
+                    guard.defer_destroy(head);
+                    return Some(value);
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let _guard = epoch::pin();
+        let head = self.head.load(Ordering::Acquire);
+        unsafe { (*head).next.load(Ordering::Acquire).is_null() }
+    }
+}
+
+impl<T> Drop for MsQueue<T> {
+    fn drop(&mut self) {
+        unsafe {
+            // The sentinel has no value.
+            let sentinel = Box::from_raw(*self.head.get_mut());
+            let mut node = sentinel.next.load(Ordering::Relaxed);
+            while !node.is_null() {
+                let mut boxed = Box::from_raw(node);
+                boxed.value.assume_init_drop();
+                node = boxed.next.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl<T> Default for MsQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for MsQueue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MsQueue")
+            .field("is_empty", &self.is_empty())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn one_thread_should_work() {
+        let queue = MsQueue::new();
+        assert!(queue.is_empty());
+        queue.push(1);
+        queue.push(2);
+        assert!(!queue.is_empty());
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn each_producer_should_keep_fifo_order() {
+        const PRODUCERS: usize = 3;
+        const PER_PRODUCER: usize = 5000;
+        let queue = MsQueue::new();
+        let popped = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for p in 0..PRODUCERS {
+                let queue = &queue;
+                s.spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        queue.push((p, i));
+                    }
+                });
+            }
+            for _ in 0..2 {
+                s.spawn(|| {
+                    let mut last = [None; PRODUCERS];
+                    while popped.load(Ordering::Relaxed) < PRODUCERS * PER_PRODUCER {
+                        match queue.pop() {
+                            Some((p, i)) => {
+                                assert!(last[p] < Some(i));
+                                last[p] = Some(i);
+                                popped.fetch_add(1, Ordering::Relaxed);
+                            }
+                            None => thread::yield_now(),
+                        }
+                    }
+                });
+            }
+        });
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn drop_should_drop_remaining_values() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop;
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let queue = MsQueue::new();
+        for _ in 0..3 {
+            queue.push(DetectDrop);
+        }
+        drop(queue.pop());
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+        drop(queue);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 3);
+    }
+}
@@ -1,20 +1,21 @@
 use std::{
     cell::UnsafeCell,
+    fmt,
     ops::{Deref, DerefMut},
     sync::atomic::{AtomicU32, Ordering},
 };
 
 use atomic_wait::{wait, wake_one};
 
-pub struct Mutex<T> {
+pub struct Mutex<T: ?Sized> {
     /// 0: unlocked
     /// 1: locked, no other threads waiting
-    /// 2: unlocked, other threads waiting
+    /// 2: locked, other threads waiting
     state: AtomicU32,
     value: UnsafeCell<T>,
 }
 
-pub struct MutexGuard<'a, T> {
+pub struct MutexGuard<'a, T: ?Sized> {
     pub(crate) mutex: &'a Mutex<T>,
 }
 
@@ -25,45 +26,96 @@ impl<T> Mutex<T> {
             value: UnsafeCell::new(value),
         }
     }
+}
 
-    pub fn lock(&self) -> MutexGuard<T> {
+impl<T: ?Sized> Mutex<T> {
+    pub fn lock(&self) -> MutexGuard<'_, T> {
         lock_contended(&self.state);
         // Swap successfully, means locked.
         MutexGuard { mutex: self }
     }
+
+    /// Tries to acquire the lock without blocking.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
 }
 
 fn lock_contended(state: &AtomicU32) {
     let mut spin_count = 0;
-    while let Err(s) = state.compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed) {
-        if s == 1 {
-            if spin_count < 100 {
-                spin_count += 1;
-                std::hint::spin_loop();
-                continue;
-            }
-            _ = state.compare_exchange(1, 2, Ordering::Acquire, Ordering::Relaxed);
-        }
+    // Spin for a while if it's locked but nobody is waiting yet.
+    while state.load(Ordering::Relaxed) == 1 && spin_count < 100 {
+        spin_count += 1;
+        std::hint::spin_loop();
+    }
+    if state
+        .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+        .is_ok()
+    {
+        return;
+    }
+    // Mark the lock as contended (2) while waiting, and keep it that way
+    // after we got it, since we can't know if other threads are still waiting.
+    while state.swap(2, Ordering::Acquire) != 0 {
         wait(state, 2)
     }
 }
 
-unsafe impl<T> Sync for Mutex<T> where T: Send {}
+unsafe impl<T: ?Sized> Sync for Mutex<T> where T: Send {}
 
-impl<T> Deref for MutexGuard<'_, T> {
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         unsafe { &*self.mutex.value.get() }
     }
 }
 
-impl<T> DerefMut for MutexGuard<'_, T> {
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         unsafe { &mut *self.mutex.value.get() }
     }
 }
 
-impl<T> Drop for MutexGuard<'_, T> {
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Mutex");
+        // Never block in `Debug`, the lock might be held by the caller.
+        match self.try_lock() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for Mutex<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for MutexGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
     fn drop(&mut self) {
         // If there are threads waiting for the lock, wait one of them.
         if self.mutex.state.swap(0, Ordering::Release) == 2 {
@@ -114,6 +166,37 @@ mod tests {
         assert_eq!(guard.len(), 2);
     }
 
+    #[test]
+    fn unlock_should_wake_every_waiter() {
+        let l = std::sync::Arc::new(Mutex::new(0));
+        let (tx, rx) = std::sync::mpsc::channel();
+
+        let guard = l.lock();
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let (l, tx) = (l.clone(), tx.clone());
+                thread::spawn(move || {
+                    *l.lock() += 1;
+                    tx.send(()).unwrap();
+                })
+            })
+            .collect();
+        // Give both threads time to go to sleep on the lock.
+        sleep(Duration::from_millis(100));
+        drop(guard);
+
+        // The first waiter to get the lock must still wake the second one.
+        // Not joining before this, so a lost wakeup fails instead of hanging.
+        for _ in 0..2 {
+            rx.recv_timeout(Duration::from_secs(5))
+                .expect("a waiter was never woken");
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(*l.lock(), 2);
+    }
+
     #[test]
     fn high_concurrency_test() {
         let l = Mutex::new(0);
@@ -132,4 +215,60 @@ mod tests {
         let guard = l.lock();
         assert_eq!(*guard, 10 * 1000);
     }
+
+    #[test]
+    fn trait_object_should_work() {
+        trait Handler: Send {
+            fn handle(&mut self, n: usize);
+            fn total(&self) -> usize;
+        }
+        struct Counter(usize);
+        impl Handler for Counter {
+            fn handle(&mut self, n: usize) {
+                self.0 += n;
+            }
+            fn total(&self) -> usize {
+                self.0
+            }
+        }
+
+        let handler: crate::Arc<Mutex<dyn Handler>> =
+            unsafe { crate::Arc::unsize(crate::Arc::new(Mutex::new(Counter(0))), |m| m as _) };
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let handler = handler.clone();
+                s.spawn(move || {
+                    for i in 0..100 {
+                        handler.lock().handle(i);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(handler.lock().total(), 4 * 4950);
+
+        let boxed: Box<Mutex<[u8]>> = Box::new(Mutex::new([1, 2, 3]));
+        boxed.lock()[0] = 4;
+        assert_eq!(&*boxed.lock(), &[4, 2, 3]);
+    }
+
+    #[test]
+    fn try_lock_should_work() {
+        let l = Mutex::new(1);
+        let guard = l.try_lock().unwrap();
+        assert!(l.try_lock().is_none());
+        drop(guard);
+        *l.try_lock().unwrap() += 1;
+        assert_eq!(*l.lock(), 2);
+    }
+
+    #[test]
+    fn debug_should_not_block() {
+        let l = Mutex::new(vec![1]);
+        assert_eq!(format!("{l:?}"), "Mutex { data: [1], .. }");
+        let guard = l.lock();
+        assert_eq!(format!("{l:?}"), "Mutex { data: <locked>, .. }");
+        assert_eq!(format!("{guard:?}"), "[1]");
+    }
 }
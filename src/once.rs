@@ -0,0 +1,444 @@
+use std::{
+    cell::{Cell, UnsafeCell},
+    fmt,
+    mem::MaybeUninit,
+    ops::Deref,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use atomic_wait::{wait, wake_all};
+
+/// Runs an initialization exactly once, other callers wait until it's done.
+pub struct Once {
+    /// One of the constants below.
+    state: AtomicU32,
+}
+
+const INCOMPLETE: u32 = 0;
+/// A previous initialization panicked.
+const POISONED: u32 = 1;
+/// Running, no other threads waiting.
+const RUNNING: u32 = 2;
+/// Running, other threads waiting.
+const QUEUED: u32 = 3;
+const COMPLETE: u32 = 4;
+
+/// Passed to the closure of `Once::call_once_force`.
+#[derive(Debug)]
+pub struct OnceState {
+    poisoned: bool,
+}
+
+impl OnceState {
+    /// Returns `true` if a previous initialization panicked.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(INCOMPLETE),
+        }
+    }
+
+    /// Runs `f` if this is the first call, otherwise waits until the
+    /// first call has finished.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a previous initialization panicked.
+    pub fn call_once(&self, f: impl FnOnce()) {
+        if self.is_completed() {
+            return;
+        }
+        let mut f = Some(f);
+        self.call(false, &mut |_| {
+            (f.take().unwrap())();
+            true
+        });
+    }
+
+    /// Like `call_once`, but also runs `f` if a previous initialization
+    /// panicked, and completes the `Once` if `f` returns.
+    pub fn call_once_force(&self, f: impl FnOnce(&OnceState)) {
+        if self.is_completed() {
+            return;
+        }
+        let mut f = Some(f);
+        self.call(true, &mut |state| {
+            (f.take().unwrap())(state);
+            true
+        });
+    }
+
+    /// Returns `true` if an initialization has completed.
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    /// Runs `f` unless completed, `f` returns `false` to
+    /// leave the `Once` incomplete.
+    fn call(&self, ignore_poison: bool, f: &mut dyn FnMut(&OnceState) -> bool) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            match state {
+                COMPLETE => return,
+                POISONED if !ignore_poison => panic!("Once instance has previously been poisoned"),
+                INCOMPLETE | POISONED => {
+                    if let Err(s) = self.state.compare_exchange(
+                        state,
+                        RUNNING,
+                        Ordering::Acquire,
+                        Ordering::Acquire,
+                    ) {
+                        state = s;
+                        continue;
+                    }
+                    // Poisons the `Once` if `f` panics.
+                    let mut guard = Finish {
+                        state: &self.state,
+                        set_to: POISONED,
+                    };
+                    let done = f(&OnceState {
+                        poisoned: state == POISONED,
+                    });
+                    guard.set_to = if done { COMPLETE } else { INCOMPLETE };
+                    return;
+                }
+                RUNNING => {
+                    if let Err(s) = self.state.compare_exchange(
+                        RUNNING,
+                        QUEUED,
+                        Ordering::Acquire,
+                        Ordering::Acquire,
+                    ) {
+                        state = s;
+                        continue;
+                    }
+                    state = QUEUED;
+                }
+                _ => {
+                    wait(&self.state, QUEUED);
+                    state = self.state.load(Ordering::Acquire);
+                }
+            }
+        }
+    }
+}
+
+/// Sets the final state of a running `Once` and wakes the waiters.
+struct Finish<'a> {
+    state: &'a AtomicU32,
+    set_to: u32,
+}
+
+impl Drop for Finish<'_> {
+    fn drop(&mut self) {
+        // Release matches the Acquire loads of the waiters,
+        // so they see everything the initialization did.
+        if self.state.swap(self.set_to, Ordering::Release) == QUEUED {
+            wake_all(self.state);
+        }
+    }
+}
+
+/// A value that is initialized at most once.
+pub struct OnceLock<T> {
+    once: Once,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for OnceLock<T> {}
+unsafe impl<T: Send> Send for OnceLock<T> {}
+
+impl<T> OnceLock<T> {
+    pub const fn new() -> Self {
+        Self {
+            once: Once::new(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Gets the value if it has been initialized.
+    pub fn get(&self) -> Option<&T> {
+        if self.once.is_completed() {
+            // Safety: The value is initialized and never changes anymore.
+            Some(unsafe { (*self.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.once.is_completed() {
+            Some(unsafe { self.value.get_mut().assume_init_mut() })
+        } else {
+            None
+        }
+    }
+
+    /// Sets the value, or gives it back if it was already initialized.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    /// Gets the value, initializing it with `f` if needed.
+    ///
+    /// If `f` panics, the panic is propagated and the next caller
+    /// tries to initialize it again.
+    pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        match self.get_or_try_init(|| Ok::<T, std::convert::Infallible>(f())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Gets the value, initializing it with `f` if needed.
+    ///
+    /// If `f` fails, the error is returned and the value stays uninitialized.
+    pub fn get_or_try_init<E>(&self, f: impl FnOnce() -> Result<T, E>) -> Result<&T, E> {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+        let mut f = Some(f);
+        let mut result = Ok(());
+        self.once.call(true, &mut |_| match (f.take().unwrap())() {
+            Ok(value) => {
+                // Safety: We're the only thread running the initialization.
+                unsafe { (*self.value.get()).write(value) };
+                true
+            }
+            Err(e) => {
+                result = Err(e);
+                false
+            }
+        });
+        result.map(|()| self.get().unwrap())
+    }
+
+    pub fn into_inner(mut self) -> Option<T> {
+        self.take()
+    }
+
+    /// Takes the value out, leaving the `OnceLock` uninitialized.
+    pub fn take(&mut self) -> Option<T> {
+        if !self.once.is_completed() {
+            return None;
+        }
+        self.once = Once::new();
+        // Safety: The value was initialized, and the `Once` was reset
+        // so it won't be read or dropped again.
+        Some(unsafe { self.value.get_mut().assume_init_read() })
+    }
+}
+
+impl<T> Drop for OnceLock<T> {
+    fn drop(&mut self) {
+        if self.once.is_completed() {
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+/// A value that is initialized on first access.
+///
+/// ```
+/// use conutils::Lazy;
+///
+/// static GREETING: Lazy<String> = Lazy::new(|| "hello".to_uppercase());
+/// assert_eq!(*GREETING, "HELLO");
+/// ```
+pub struct Lazy<T, F = fn() -> T> {
+    cell: OnceLock<T>,
+    /// Taken by the thread running the initialization.
+    init: Cell<Option<F>>,
+}
+
+// Safety: `init` is only accessed while running the initialization,
+// which `OnceLock` makes exclusive.
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    pub const fn new(init: F) -> Self {
+        Self {
+            cell: OnceLock::new(),
+            init: Cell::new(Some(init)),
+        }
+    }
+
+    /// Forces the initialization and returns the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a previous initialization panicked.
+    pub fn force(this: &Self) -> &T {
+        this.cell.get_or_init(|| match this.init.take() {
+            Some(init) => init(),
+            None => panic!("Lazy instance has previously been poisoned"),
+        })
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        Lazy::force(self)
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Default for OnceLock<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<T> for OnceLock<T> {
+    fn from(value: T) -> Self {
+        let cell = Self::new();
+        let _ = cell.set(value);
+        cell
+    }
+}
+
+impl<T: Default> Default for Lazy<T> {
+    fn default() -> Self {
+        Self::new(T::default)
+    }
+}
+
+impl fmt::Debug for Once {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Once")
+            .field("completed", &self.is_completed())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_tuple("OnceLock");
+        match self.get() {
+            Some(value) => d.field(value),
+            None => d.field(&format_args!("<uninit>")),
+        };
+        d.finish()
+    }
+}
+
+impl<T: fmt::Debug, F> fmt::Debug for Lazy<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_tuple("Lazy");
+        match self.cell.get() {
+            Some(value) => d.field(value),
+            None => d.field(&format_args!("<uninit>")),
+        };
+        d.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        panic::{catch_unwind, AssertUnwindSafe},
+        sync::atomic::AtomicUsize,
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[test]
+    fn once_should_run_exactly_once() {
+        let once = Once::new();
+        let calls = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    once.call_once(|| {
+                        thread::sleep(Duration::from_millis(10));
+                        calls.fetch_add(1, Ordering::Relaxed);
+                    });
+                    // Everyone waits until the initialization is done.
+                    assert_eq!(calls.load(Ordering::Relaxed), 1);
+                });
+            }
+        });
+        assert!(once.is_completed());
+    }
+
+    #[test]
+    fn once_should_be_poisoned_by_panic() {
+        let once = Once::new();
+        let r = catch_unwind(AssertUnwindSafe(|| once.call_once(|| panic!("oops"))));
+        assert!(r.is_err());
+        assert!(!once.is_completed());
+
+        let r = catch_unwind(AssertUnwindSafe(|| once.call_once(|| {})));
+        assert!(r.is_err());
+
+        let mut poisoned = false;
+        once.call_once_force(|state| poisoned = state.is_poisoned());
+        assert!(poisoned);
+        assert!(once.is_completed());
+        once.call_once(|| unreachable!());
+    }
+
+    #[test]
+    fn once_lock_should_work() {
+        let cell = OnceLock::new();
+        assert_eq!(cell.get(), None);
+        let values: Vec<usize> = thread::scope(|s| {
+            let cell = &cell;
+            let handles: Vec<_> = (0..4)
+                .map(|i| s.spawn(move || *cell.get_or_init(|| i)))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert!(values.iter().all(|&v| v == values[0]));
+        assert_eq!(cell.set(10), Err(10));
+        assert_eq!(cell.into_inner(), Some(values[0]));
+    }
+
+    #[test]
+    fn get_or_try_init_should_retry_after_error() {
+        let cell = OnceLock::new();
+        assert_eq!(cell.get_or_try_init(|| Err("nope")), Err("nope"));
+        assert_eq!(cell.get(), None);
+        let r = catch_unwind(AssertUnwindSafe(|| cell.get_or_init(|| panic!("oops"))));
+        assert!(r.is_err());
+        assert_eq!(
+            cell.get_or_try_init(|| Ok::<_, ()>(String::from("ok"))),
+            Ok(&String::from("ok"))
+        );
+    }
+
+    #[test]
+    fn lazy_should_initialize_once() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        static VALUE: Lazy<Vec<usize>> = Lazy::new(|| {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            vec![1, 2, 3]
+        });
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| assert_eq!(VALUE.len(), 3));
+            }
+        });
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+        assert_eq!(format!("{:?}", *VALUE), "[1, 2, 3]");
+    }
+}
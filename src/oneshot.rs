@@ -1,19 +1,20 @@
 use std::{
     cell::UnsafeCell,
-    marker::PhantomData,
+    fmt,
     mem::MaybeUninit,
     sync::atomic::{AtomicBool, Ordering},
-    thread::{self, Thread},
 };
 
+use crate::{Parker, Unparker};
+
 pub struct Sender<'a, T> {
     channel: &'a Channel<T>,
-    receiving_thread: Thread,
+    unparker: Unparker,
 }
 
 pub struct Receiver<'a, T> {
     channel: &'a Channel<T>,
-    _no_send: PhantomData<*const ()>,
+    parker: Parker,
 }
 
 pub struct Channel<T> {
@@ -33,14 +34,15 @@ impl<T> Channel<T> {
 
     pub fn split(&'_ mut self) -> (Sender<'_, T>, Receiver<'_, T>) {
         *self = Self::new();
+        let parker = Parker::new();
         (
             Sender {
                 channel: self,
-                receiving_thread: thread::current(),
+                unparker: parker.unparker(),
             },
             Receiver {
                 channel: self,
-                _no_send: PhantomData,
+                parker,
             },
         )
     }
@@ -50,15 +52,15 @@ impl<T> Sender<'_, T> {
     /// This never panics. :)
     pub fn send(self, message: T) {
         unsafe { (*self.channel.message.get()).write(message) };
-        self.channel.ready.store(true, Ordering::Relaxed);
-        self.receiving_thread.unpark();
+        self.channel.ready.store(true, Ordering::Release);
+        self.unparker.unpark();
     }
 }
 
 impl<T> Receiver<'_, T> {
     pub fn receive(self) -> T {
-        if !self.channel.ready.load(Ordering::Acquire) {
-            thread::park();
+        while !self.channel.ready.load(Ordering::Acquire) {
+            self.parker.park();
         }
         unsafe { (*self.channel.message.get()).assume_init_read() }
     }
@@ -74,6 +76,26 @@ impl<T> Drop for Channel<T> {
     }
 }
 
+impl<T> fmt::Debug for Channel<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Channel")
+            .field("ready", &self.ready.load(Ordering::Relaxed))
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for Sender<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for Receiver<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
 impl<T> Default for Channel<T> {
     fn default() -> Self {
         Self::new()
@@ -106,4 +128,30 @@ mod tests {
         });
         assert_eq!(receiver.receive(), 1);
     }
+
+    #[test]
+    fn receiver_should_be_sendable() {
+        let mut channel = Channel::new();
+        let (sender, receiver) = channel.split();
+        thread::scope(|s| {
+            // Neither side has to stay on the thread that called `split`.
+            let t = s.spawn(move || receiver.receive());
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                sender.send(1);
+            });
+            assert_eq!(t.join().unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn debug_should_work() {
+        let mut channel = Channel::new();
+        let (sender, receiver) = channel.split();
+        assert_eq!(format!("{sender:?}"), "Sender { .. }");
+        assert_eq!(format!("{receiver:?}"), "Receiver { .. }");
+        sender.send(1);
+        assert_eq!(receiver.receive(), 1);
+        assert_eq!(format!("{channel:?}"), "Channel { ready: true, .. }");
+    }
 }
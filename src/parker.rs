@@ -0,0 +1,192 @@
+use std::{
+    cell::Cell,
+    fmt,
+    marker::PhantomData,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+use atomic_wait::{wait, wake_one};
+
+use crate::{futex, Arc};
+
+const EMPTY: u32 = 0;
+const NOTIFIED: u32 = 1;
+const PARKED: u32 = 2;
+
+/// Blocks the thread that owns it until an `Unparker` hands it a token.
+///
+/// Unlike `thread::park`, it isn't tied to a thread: the `Parker` can be
+/// moved to whichever thread needs to wait.
+pub struct Parker {
+    state: Arc<AtomicU32>,
+    /// Only one thread may park at a time.
+    _no_sync: PhantomData<Cell<()>>,
+}
+
+/// Wakes up the `Parker` it was created from.
+#[derive(Clone)]
+pub struct Unparker {
+    state: Arc<AtomicU32>,
+}
+
+impl Parker {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(AtomicU32::new(EMPTY)),
+            _no_sync: PhantomData,
+        }
+    }
+
+    pub fn unparker(&self) -> Unparker {
+        Unparker {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Blocks until the token is available, then consumes it.
+    ///
+    /// Returns right away if `unpark` was called since the last `park`.
+    pub fn park(&self) {
+        self.park_until(None);
+    }
+
+    /// Like `park`, but gives up after `timeout`.
+    ///
+    /// Returns `true` if the token was consumed. A timeout too large to
+    /// represent parks forever.
+    pub fn park_timeout(&self, timeout: Duration) -> bool {
+        self.park_until(Instant::now().checked_add(timeout))
+    }
+
+    /// Like `park`, but gives up at `deadline`.
+    ///
+    /// Returns `true` if the token was consumed.
+    pub fn park_deadline(&self, deadline: Instant) -> bool {
+        self.park_until(Some(deadline))
+    }
+
+    fn park_until(&self, deadline: Option<Instant>) -> bool {
+        // Acquire matches the Release swap in `unpark`.
+        if self
+            .state
+            .compare_exchange(NOTIFIED, EMPTY, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            return true;
+        }
+        if self
+            .state
+            .compare_exchange(EMPTY, PARKED, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            // Got notified in the meantime.
+            self.state.swap(EMPTY, Ordering::Acquire);
+            return true;
+        }
+        loop {
+            match deadline {
+                None => wait(&self.state, PARKED),
+                Some(deadline) => {
+                    if !futex::wait_until(&self.state, PARKED, deadline) {
+                        // Take the token if it arrived just now.
+                        return self.state.swap(EMPTY, Ordering::Acquire) == NOTIFIED;
+                    }
+                }
+            }
+            if self
+                .state
+                .compare_exchange(NOTIFIED, EMPTY, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return true;
+            }
+            // Spurious wake up, park again.
+        }
+    }
+}
+
+impl Unparker {
+    /// Makes the token available, waking up the `Parker` if it's parked.
+    pub fn unpark(&self) {
+        if self.state.swap(NOTIFIED, Ordering::Release) == PARKED {
+            wake_one(&*self.state);
+        }
+    }
+}
+
+impl Default for Parker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Parker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Parker").finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for Unparker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Unparker").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn parker_should_work() {
+        let parker = Parker::new();
+        let unparker = parker.unparker();
+
+        // The token is kept until the next park.
+        unparker.unpark();
+        unparker.unpark();
+        parker.park();
+        assert!(!parker.park_timeout(Duration::from_millis(10)));
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                unparker.unpark();
+            });
+            parker.park();
+        });
+    }
+
+    #[test]
+    fn parker_should_be_movable_to_other_threads() {
+        let parker = Parker::new();
+        let unparker = parker.unparker();
+        let t = thread::spawn(move || parker.park_timeout(Duration::from_secs(10)));
+        thread::sleep(Duration::from_millis(10));
+        unparker.unpark();
+        assert!(t.join().unwrap());
+    }
+
+    #[test]
+    fn park_deadline_should_time_out() {
+        let parker = Parker::new();
+        let deadline = Instant::now() + Duration::from_millis(10);
+        assert!(!parker.park_deadline(deadline));
+        assert!(Instant::now() >= deadline);
+    }
+
+    #[test]
+    fn huge_timeout_should_park_forever() {
+        let parker = Parker::new();
+        let unparker = parker.unparker();
+        thread::scope(|s| {
+            s.spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                unparker.unpark();
+            });
+            assert!(parker.park_timeout(Duration::MAX));
+        });
+    }
+}
@@ -1,12 +1,13 @@
 use std::{
     cell::UnsafeCell,
+    fmt,
     ops::{Deref, DerefMut},
     sync::atomic::{AtomicU32, Ordering},
 };
 
 use atomic_wait::{wait, wake_all, wake_one};
 
-pub struct RwLock<T> {
+pub struct RwLock<T: ?Sized> {
     /// The number of read locks times two, plus one if there's a writer waiting.
     /// u32::MAX if write locked.
     ///
@@ -18,15 +19,15 @@ pub struct RwLock<T> {
     value: UnsafeCell<T>,
 }
 
-pub struct ReadGuard<'a, T> {
+pub struct ReadGuard<'a, T: ?Sized> {
     rwmutex: &'a RwLock<T>,
 }
 
-pub struct WriteGuard<'a, T> {
+pub struct WriteGuard<'a, T: ?Sized> {
     rwmutx: &'a RwLock<T>,
 }
 
-unsafe impl<T> Sync for RwLock<T> where T: Send + Sync {}
+unsafe impl<T: ?Sized> Sync for RwLock<T> where T: Send + Sync {}
 
 impl<T> RwLock<T> {
     pub const fn new(value: T) -> Self {
@@ -36,8 +37,10 @@ impl<T> RwLock<T> {
             value: UnsafeCell::new(value),
         }
     }
+}
 
-    pub fn read(&self) -> ReadGuard<T> {
+impl<T: ?Sized> RwLock<T> {
+    pub fn read(&self) -> ReadGuard<'_, T> {
         let mut s = self.state.load(Ordering::Relaxed);
         loop {
             // unlocked or read locked
@@ -60,7 +63,7 @@ impl<T> RwLock<T> {
         }
     }
 
-    pub fn write(&self) -> WriteGuard<T> {
+    pub fn write(&self) -> WriteGuard<'_, T> {
         let mut s = self.state.load(Ordering::Relaxed);
         loop {
             // Try to lock if unlocked
@@ -100,27 +103,110 @@ impl<T> RwLock<T> {
     }
 }
 
-impl<T> Deref for ReadGuard<'_, T> {
+impl<T: ?Sized> RwLock<T> {
+    /// Tries to acquire a read lock without blocking.
+    ///
+    /// Fails if the lock is write locked or a writer is waiting.
+    pub fn try_read(&self) -> Option<ReadGuard<'_, T>> {
+        let mut s = self.state.load(Ordering::Relaxed);
+        while s % 2 == 0 {
+            assert!(s != u32::MAX - 2, "too many readers");
+            match self
+                .state
+                .compare_exchange(s, s + 2, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return Some(ReadGuard { rwmutex: self }),
+                Err(e) => s = e,
+            }
+        }
+        None
+    }
+
+    /// Tries to acquire the write lock without blocking.
+    pub fn try_write(&self) -> Option<WriteGuard<'_, T>> {
+        let mut s = self.state.load(Ordering::Relaxed);
+        while s <= 1 {
+            match self
+                .state
+                .compare_exchange(s, u32::MAX, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return Some(WriteGuard { rwmutx: self }),
+                Err(e) => s = e,
+            }
+        }
+        None
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("RwLock");
+        // Never block in `Debug`, the lock might be held by the caller.
+        match self.try_read() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for RwLock<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for ReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for ReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for WriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for WriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized> Deref for ReadGuard<'_, T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         unsafe { &*self.rwmutex.value.get() }
     }
 }
 
-impl<T> Deref for WriteGuard<'_, T> {
+impl<T: ?Sized> Deref for WriteGuard<'_, T> {
     type Target = T;
     fn deref(&self) -> &Self::Target {
         unsafe { &*self.rwmutx.value.get() }
     }
 }
 
-impl<T> DerefMut for WriteGuard<'_, T> {
+impl<T: ?Sized> DerefMut for WriteGuard<'_, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         unsafe { &mut *self.rwmutx.value.get() }
     }
 }
 
-impl<T> Drop for ReadGuard<'_, T> {
+impl<T: ?Sized> Drop for ReadGuard<'_, T> {
     fn drop(&mut self) {
         // Decrement the state by 2 to remove one read-lock.
         if self.rwmutex.state.fetch_sub(2, Ordering::Release) == 3 {
@@ -135,7 +221,7 @@ impl<T> Drop for ReadGuard<'_, T> {
     }
 }
 
-impl<T> Drop for WriteGuard<'_, T> {
+impl<T: ?Sized> Drop for WriteGuard<'_, T> {
     fn drop(&mut self) {
         self.rwmutx.state.store(0, Ordering::Release);
         self.rwmutx
@@ -255,4 +341,38 @@ mod tests {
         let rg3 = rw.read();
         assert_eq!(*rg3, 1)
     }
+
+    #[test]
+    fn unsized_value_should_work() {
+        let rw: Box<RwLock<[i32]>> = Box::new(RwLock::new([1, 2, 3]));
+        {
+            let r1 = rw.read();
+            let r2 = rw.read();
+            assert_eq!(r1.len(), 3);
+            assert_eq!(r2.iter().sum::<i32>(), 6);
+        }
+        rw.write().sort_by(|a, b| b.cmp(a));
+        assert_eq!(&*rw.read(), &[3, 2, 1]);
+
+        let rw: &RwLock<dyn ToString + Sync> = &RwLock::new(42);
+        assert_eq!(rw.read().to_string(), "42");
+    }
+
+    #[test]
+    fn try_lock_and_debug_should_work() {
+        let rw = RwLock::new(1);
+        assert_eq!(format!("{rw:?}"), "RwLock { data: 1, .. }");
+
+        let r = rw.try_read().unwrap();
+        assert!(rw.try_read().is_some());
+        assert!(rw.try_write().is_none());
+        assert_eq!(format!("{rw:?}"), "RwLock { data: 1, .. }");
+        drop(r);
+
+        let w = rw.try_write().unwrap();
+        assert!(rw.try_read().is_none());
+        assert!(rw.try_write().is_none());
+        assert_eq!(format!("{rw:?}"), "RwLock { data: <locked>, .. }");
+        assert_eq!(format!("{w:?}"), "1");
+    }
 }
@@ -0,0 +1,238 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+use atomic_wait::{wait, wake_all};
+
+use crate::futex;
+
+pub struct Semaphore {
+    /// Number of available permits.
+    permits: AtomicU32,
+    /// Number of threads waiting for permits.
+    num_waiters: AtomicU32,
+}
+
+/// Gives the permits back to the semaphore when dropped.
+#[must_use = "if unused the permits are released immediately"]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: u32,
+}
+
+impl Semaphore {
+    pub const fn new(permits: u32) -> Self {
+        Self {
+            permits: AtomicU32::new(permits),
+            num_waiters: AtomicU32::new(0),
+        }
+    }
+
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Acquires `n` permits at once, blocking until they're all available.
+    pub fn acquire_many(&self, n: u32) -> SemaphorePermit<'_> {
+        self.acquire_until(n, None);
+        SemaphorePermit {
+            semaphore: self,
+            permits: n,
+        }
+    }
+
+    /// Tries to acquire a permit without blocking.
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    /// Tries to acquire `n` permits at once without blocking.
+    pub fn try_acquire_many(&self, n: u32) -> Option<SemaphorePermit<'_>> {
+        self.try_take(n).then_some(SemaphorePermit {
+            semaphore: self,
+            permits: n,
+        })
+    }
+
+    /// Acquires a permit, giving up after `timeout`.
+    ///
+    /// A timeout too large to represent waits forever.
+    pub fn acquire_timeout(&self, timeout: Duration) -> Option<SemaphorePermit<'_>> {
+        self.acquire_until(1, Instant::now().checked_add(timeout))
+            .then_some(SemaphorePermit {
+                semaphore: self,
+                permits: 1,
+            })
+    }
+
+    /// Adds `n` permits, waking up threads waiting for them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of available permits would overflow a `u32`.
+    pub fn add_permits(&self, n: u32) {
+        // SeqCst on both sides, so either we see the waiter or
+        // the waiter sees the new permits before going to sleep.
+        self.permits
+            .fetch_update(Ordering::SeqCst, Ordering::Relaxed, |p| p.checked_add(n))
+            .expect("too many permits");
+        if self.num_waiters.load(Ordering::SeqCst) > 0 {
+            // Waiters might need different numbers of permits,
+            // so let all of them check.
+            wake_all(&self.permits);
+        }
+    }
+
+    /// Gets the number of permits currently available.
+    pub fn available_permits(&self) -> u32 {
+        self.permits.load(Ordering::Relaxed)
+    }
+
+    fn try_take(&self, n: u32) -> bool {
+        self.permits
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |p| p.checked_sub(n))
+            .is_ok()
+    }
+
+    /// Takes `n` permits, returns `false` if `deadline` passed first.
+    fn acquire_until(&self, n: u32, deadline: Option<Instant>) -> bool {
+        loop {
+            if self.try_take(n) {
+                return true;
+            }
+            self.num_waiters.fetch_add(1, Ordering::SeqCst);
+            let permits = self.permits.load(Ordering::SeqCst);
+            let timed_out = if permits >= n {
+                false
+            } else if let Some(deadline) = deadline {
+                !futex::wait_until(&self.permits, permits, deadline)
+            } else {
+                wait(&self.permits, permits);
+                false
+            };
+            self.num_waiters.fetch_sub(1, Ordering::Relaxed);
+            if timed_out {
+                return self.try_take(n);
+            }
+        }
+    }
+}
+
+impl SemaphorePermit<'_> {
+    /// Gets the number of permits held by this guard.
+    pub fn num_permits(&self) -> u32 {
+        self.permits
+    }
+
+    /// Drops the guard without giving the permits back.
+    pub fn forget(self) {
+        std::mem::forget(self)
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(self.permits);
+    }
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("permits", &self.available_permits())
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Debug for SemaphorePermit<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SemaphorePermit")
+            .field("permits", &self.permits)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicUsize, thread};
+
+    use super::*;
+
+    #[test]
+    fn semaphore_should_limit_concurrency() {
+        let semaphore = Semaphore::new(2);
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        let _permit = semaphore.acquire();
+                        let n = running.fetch_add(1, Ordering::Relaxed) + 1;
+                        max_running.fetch_max(n, Ordering::Relaxed);
+                        thread::yield_now();
+                        running.fetch_sub(1, Ordering::Relaxed);
+                    }
+                });
+            }
+        });
+
+        assert!(max_running.load(Ordering::Relaxed) <= 2);
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+
+    #[test]
+    fn try_acquire_should_work() {
+        let semaphore = Semaphore::new(1);
+        let permit = semaphore.try_acquire().unwrap();
+        assert!(semaphore.try_acquire().is_none());
+        drop(permit);
+        assert!(semaphore.try_acquire().is_some());
+    }
+
+    #[test]
+    fn acquire_timeout_should_work() {
+        let semaphore = Semaphore::new(0);
+        let start = Instant::now();
+        assert!(semaphore
+            .acquire_timeout(Duration::from_millis(20))
+            .is_none());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                semaphore.add_permits(1);
+            });
+            assert!(semaphore.acquire_timeout(Duration::from_secs(10)).is_some());
+        });
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                semaphore.add_permits(1);
+            });
+            assert!(semaphore.acquire_timeout(Duration::MAX).is_some());
+        });
+    }
+
+    #[test]
+    fn acquire_many_should_wait_for_all_permits() {
+        let semaphore = Semaphore::new(1);
+        thread::scope(|s| {
+            let t = s.spawn(|| semaphore.acquire_many(3).num_permits());
+            semaphore.add_permits(1);
+            thread::sleep(Duration::from_millis(10));
+            assert!(!t.is_finished());
+            semaphore.add_permits(1);
+            assert_eq!(t.join().unwrap(), 3);
+        });
+        assert_eq!(semaphore.available_permits(), 3);
+
+        semaphore.acquire().forget();
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+}
@@ -0,0 +1,227 @@
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    mem::MaybeUninit,
+    ops::{Deref, DerefMut},
+    sync::atomic::{fence, AtomicUsize, Ordering},
+};
+
+/// A sequence lock for small `Copy` data that is read much more often
+/// than it's written.
+///
+/// Readers never write to shared memory: they copy the data and retry if a
+/// writer was active in the meantime. Writers are never blocked by readers,
+/// only by other writers.
+pub struct SeqLock<T> {
+    /// Odd while a writer is active, incremented by two for every write.
+    seq: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+pub struct SeqLockWriteGuard<'a, T> {
+    lock: &'a SeqLock<T>,
+    /// The (odd) sequence number while we're writing.
+    seq: usize,
+}
+
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Gets a copy of the data, retrying while a write is in progress.
+    pub fn read(&self) -> T {
+        loop {
+            if let Some(value) = self.try_read() {
+                return value;
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    /// Gets a copy of the data, or `None` if a write was in progress.
+    pub fn try_read(&self) -> Option<T> {
+        // Acquire matches the Release store of the last writer.
+        let seq = self.seq.load(Ordering::Acquire);
+        if seq & 1 == 1 {
+            return None;
+        }
+        // Safety: The copy might be torn by a concurrent writer, so it stays
+        // a `MaybeUninit` until the sequence number tells us it wasn't.
+        let value = unsafe { std::ptr::read_volatile(self.value.get() as *const MaybeUninit<T>) };
+        // Matches the Release fence in `write`: if we've read anything
+        // a writer wrote, we'll see its odd sequence number below.
+        fence(Ordering::Acquire);
+        if self.seq.load(Ordering::Relaxed) != seq {
+            return None;
+        }
+        Some(unsafe { value.assume_init() })
+    }
+
+    /// Locks out other writers and returns a guard to modify the data.
+    pub fn write(&self) -> SeqLockWriteGuard<'_, T> {
+        loop {
+            if let Some(guard) = self.try_write() {
+                return guard;
+            }
+            std::hint::spin_loop();
+        }
+    }
+
+    /// Tries to start writing, without spinning if another writer is active.
+    pub fn try_write(&self) -> Option<SeqLockWriteGuard<'_, T>> {
+        let seq = self.seq.load(Ordering::Relaxed);
+        if seq & 1 == 1 {
+            return None;
+        }
+        // Acquire matches the Release store of the previous writer.
+        self.seq
+            .compare_exchange(seq, seq + 1, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        // Make sure readers that see our writes also see the odd number.
+        fence(Ordering::Release);
+        Some(SeqLockWriteGuard {
+            lock: self,
+            seq: seq + 1,
+        })
+    }
+
+    /// Replaces the data.
+    pub fn set(&self, value: T) {
+        *self.write() = value;
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T> Deref for SeqLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: We're the only writer, and readers only copy the data.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for SeqLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for SeqLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        // Release matches the Acquire loads in `try_read` and `try_write`.
+        self.lock.seq.store(self.seq + 1, Ordering::Release);
+    }
+}
+
+impl<T: Copy + fmt::Debug> fmt::Debug for SeqLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("SeqLock");
+        // Never spin in `Debug`, a write might be held by the caller.
+        match self.try_read() {
+            Some(value) => d.field("data", &value),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+impl<T: Copy + Default> Default for SeqLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Copy> From<T> for SeqLock<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SeqLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for SeqLockWriteGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::atomic::AtomicBool, thread};
+
+    use super::*;
+
+    #[test]
+    fn seqlock_should_work() {
+        let lock = SeqLock::new((1, 2));
+        assert_eq!(lock.read(), (1, 2));
+        let mut guard = lock.write();
+        guard.0 = 3;
+        // Readers can't get a value while a write is in progress.
+        assert_eq!(lock.try_read(), None);
+        assert!(lock.try_write().is_none());
+        assert_eq!(format!("{lock:?}"), "SeqLock { data: <locked>, .. }");
+        drop(guard);
+        assert_eq!(lock.read(), (3, 2));
+        lock.set((4, 5));
+        assert_eq!(lock.into_inner(), (4, 5));
+    }
+
+    #[test]
+    fn read_should_never_be_torn() {
+        const WRITES: u64 = 20_000;
+        let lock = SeqLock::new([0u64; 16]);
+        let done = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    let mut last = 0;
+                    while !done.load(Ordering::Relaxed) {
+                        let value = lock.read();
+                        // A torn read would mix values of different writes.
+                        assert!(value.iter().all(|&v| v == value[0]));
+                        assert!(value[0] >= last);
+                        last = value[0];
+                    }
+                });
+            }
+            for _ in 0..2 {
+                s.spawn(|| {
+                    for _ in 0..WRITES / 2 {
+                        let mut guard = lock.write();
+                        let next = guard[0] + 1;
+                        for v in guard.iter_mut() {
+                            *v = next;
+                        }
+                    }
+                });
+            }
+            // Wait for both writers before stopping the readers.
+            while lock.read()[0] != WRITES {
+                thread::yield_now();
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+
+        assert_eq!(lock.read(), [WRITES; 16]);
+    }
+}
@@ -1,19 +1,20 @@
 use std::{
     cell::UnsafeCell,
+    fmt,
     ops::{Deref, DerefMut},
     sync::atomic::{AtomicBool, Ordering},
 };
 
-pub struct SpinLock<T> {
+pub struct SpinLock<T: ?Sized> {
     locked: AtomicBool,
     value: UnsafeCell<T>,
 }
 
-pub struct Guard<'a, T> {
+pub struct Guard<'a, T: ?Sized> {
     lock: &'a SpinLock<T>,
 }
 
-impl<T> Drop for Guard<'_, T> {
+impl<T: ?Sized> Drop for Guard<'_, T> {
     fn drop(&mut self) {
         unsafe { self.lock.unlock() };
     }
@@ -22,7 +23,7 @@ impl<T> Drop for Guard<'_, T> {
 /// implement `Sync` for `SpinLock<T>` in order to make it shareable across threads
 /// And we need T implements `Send` in order to make it movable across threads
 /// We don't need T to be `Sync` because we will only allow one thread to access the value at a time
-unsafe impl<T> Sync for SpinLock<T> where T: Send {}
+unsafe impl<T: ?Sized> Sync for SpinLock<T> where T: Send {}
 
 impl<T> SpinLock<T> {
     pub fn new(value: T) -> Self {
@@ -31,14 +32,24 @@ impl<T> SpinLock<T> {
             value: UnsafeCell::new(value),
         }
     }
+}
 
-    pub fn lock(&self) -> Guard<T> {
+impl<T: ?Sized> SpinLock<T> {
+    pub fn lock(&self) -> Guard<'_, T> {
         while self.locked.swap(true, Ordering::Acquire) {
             std::hint::spin_loop();
         }
         Guard { lock: self }
     }
 
+    /// Tries to acquire the lock without spinning.
+    pub fn try_lock(&self) -> Option<Guard<'_, T>> {
+        if self.locked.swap(true, Ordering::Acquire) {
+            return None;
+        }
+        Some(Guard { lock: self })
+    }
+
     /// # Safety
     ///
     /// The &mut T from lock() must be gone!
@@ -48,7 +59,7 @@ impl<T> SpinLock<T> {
     }
 }
 
-impl<T> Deref for Guard<'_, T> {
+impl<T: ?Sized> Deref for Guard<'_, T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -60,7 +71,7 @@ impl<T> Deref for Guard<'_, T> {
     }
 }
 
-impl<T> DerefMut for Guard<'_, T> {
+impl<T: ?Sized> DerefMut for Guard<'_, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
         // # Safety
         //
@@ -70,6 +81,42 @@ impl<T> DerefMut for Guard<'_, T> {
     }
 }
 
+impl<T: ?Sized + fmt::Debug> fmt::Debug for SpinLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("SpinLock");
+        // Never spin in `Debug`, the lock might be held by the caller.
+        match self.try_lock() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+impl<T: Default> Default for SpinLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for SpinLock<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Guard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for Guard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::thread;
@@ -104,4 +151,30 @@ mod tests {
         let b = spinlock.lock();
         assert_eq!(*b, 2);
     }
+
+    #[test]
+    fn unsized_value_should_work() {
+        let spinlock: &SpinLock<[u8]> = &SpinLock::new([0; 4]);
+        thread::scope(|s| {
+            for i in 0..4 {
+                s.spawn(move || {
+                    spinlock.lock()[i] = i as u8;
+                });
+            }
+        });
+        assert_eq!(&*spinlock.lock(), &[0, 1, 2, 3]);
+
+        let spinlock: Box<SpinLock<dyn std::fmt::Write>> = Box::new(SpinLock::new(String::new()));
+        write!(spinlock.lock(), "hello").unwrap();
+    }
+
+    #[test]
+    fn try_lock_and_debug_should_work() {
+        let spinlock = SpinLock::new(1);
+        assert_eq!(format!("{spinlock:?}"), "SpinLock { data: 1, .. }");
+        let guard = spinlock.try_lock().unwrap();
+        assert!(spinlock.try_lock().is_none());
+        assert_eq!(format!("{spinlock:?}"), "SpinLock { data: <locked>, .. }");
+        assert_eq!(guard.to_string(), "1");
+    }
 }
@@ -0,0 +1,464 @@
+//! A bounded single producer single consumer channel.
+//!
+//! The messages live in a ring buffer. The sender only writes the tail and
+//! the receiver only writes the head, so neither side ever waits for the
+//! other unless the buffer is full or empty.
+
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    mem::MaybeUninit,
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use atomic_wait::{wait, wake_one};
+
+use crate::cache_padded::CachePadded;
+
+/// Shared state between the sender and the receiver.
+///
+/// Positions go from 0 to `2 * capacity` (exclusive), so that a full
+/// buffer can be told apart from an empty one.
+struct Shared<T> {
+    /// The position of the next message to receive.
+    head: CachePadded<AtomicUsize>,
+    /// The position of the next message to send.
+    tail: CachePadded<AtomicUsize>,
+    buffer: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    /// Set to 1 while the receiver waits for a message.
+    receiver_waiting: AtomicU32,
+    /// Set to 1 while the sender waits for a free slot.
+    sender_waiting: AtomicU32,
+    /// Set once either side is dropped.
+    disconnected: AtomicBool,
+}
+
+unsafe impl<T: Send> Send for Shared<T> {}
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+/// The sending half of the channel.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+    /// Our copy of `shared.tail`.
+    tail: usize,
+    /// The last head we've seen, so we don't read it on every push.
+    cached_head: usize,
+}
+
+/// The receiving half of the channel.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    /// Our copy of `shared.head`.
+    head: usize,
+    /// The last tail we've seen, so we don't read it on every pop.
+    cached_tail: usize,
+}
+
+/// Creates a channel that holds at most `capacity` messages.
+///
+/// # Panics
+///
+/// Panics if `capacity` is 0.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "capacity must be non-zero");
+    assert!(capacity <= usize::MAX / 4, "capacity overflow");
+    let shared = Arc::new(Shared {
+        head: CachePadded::new(AtomicUsize::new(0)),
+        tail: CachePadded::new(AtomicUsize::new(0)),
+        buffer: (0..capacity)
+            .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+            .collect(),
+        receiver_waiting: AtomicU32::new(0),
+        sender_waiting: AtomicU32::new(0),
+        disconnected: AtomicBool::new(false),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+            tail: 0,
+            cached_head: 0,
+        },
+        Receiver {
+            shared,
+            head: 0,
+            cached_tail: 0,
+        },
+    )
+}
+
+impl<T> Shared<T> {
+    fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Number of messages between the two positions.
+    fn distance(&self, head: usize, tail: usize) -> usize {
+        (tail + 2 * self.capacity() - head) % (2 * self.capacity())
+    }
+
+    fn advance(&self, pos: usize, n: usize) -> usize {
+        (pos + n) % (2 * self.capacity())
+    }
+
+    /// The slot of a position.
+    fn slot(&self, pos: usize) -> *mut T {
+        // Derived from the whole buffer, so the slice methods may copy
+        // past this slot.
+        unsafe { UnsafeCell::raw_get(self.buffer.as_ptr().add(pos % self.capacity())).cast() }
+    }
+
+    fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Acquire);
+        let tail = self.tail.load(Ordering::Acquire);
+        self.distance(head, tail)
+    }
+
+    /// Wakes the other side if it's waiting on `waiting`.
+    ///
+    /// Must be called after a SeqCst store of our position. Together with
+    /// the SeqCst accesses in `wait_while`, either the other side sees the
+    /// new position, or we see that it's going to sleep.
+    fn notify(&self, waiting: &AtomicU32) {
+        if waiting.load(Ordering::SeqCst) == 1 && waiting.swap(0, Ordering::Relaxed) == 1 {
+            wake_one(waiting);
+        }
+    }
+
+    /// Sleeps on `waiting` unless `ready` or disconnected.
+    ///
+    /// `ready` must load the other side's position with SeqCst.
+    fn wait_while(&self, waiting: &AtomicU32, mut ready: impl FnMut() -> bool) {
+        waiting.store(1, Ordering::SeqCst);
+        if !ready() && !self.disconnected.load(Ordering::SeqCst) {
+            // Returns right away if the other side already cleared the flag.
+            wait(waiting, 1);
+        }
+        waiting.store(0, Ordering::Relaxed);
+    }
+
+    fn disconnect(&self) {
+        self.disconnected.store(true, Ordering::SeqCst);
+        for waiting in [&self.receiver_waiting, &self.sender_waiting] {
+            if waiting.swap(0, Ordering::SeqCst) == 1 {
+                wake_one(waiting);
+            }
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Sends a message, waiting for a free slot if the channel is full.
+    ///
+    /// Gives the message back if the receiver is gone.
+    pub fn push(&mut self, mut value: T) -> Result<(), T> {
+        loop {
+            value = match self.try_push(value) {
+                Ok(()) => return Ok(()),
+                Err(value) if self.is_disconnected() => return Err(value),
+                Err(value) => value,
+            };
+            let shared = &*self.shared;
+            let tail = self.tail;
+            shared.wait_while(&shared.sender_waiting, || {
+                let head = shared.head.load(Ordering::SeqCst);
+                shared.distance(head, tail) < shared.capacity()
+            });
+        }
+    }
+
+    /// Sends a message if there's a free slot and the receiver is still there.
+    pub fn try_push(&mut self, value: T) -> Result<(), T> {
+        if self.is_disconnected() || self.free_slots(1) == 0 {
+            return Err(value);
+        }
+        // Safety: The slot is free, and only we write free slots.
+        unsafe { self.shared.slot(self.tail).write(value) };
+        self.publish(1);
+        Ok(())
+    }
+
+    /// Returns the number of messages in the channel.
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+
+    /// Returns whether the receiver is gone.
+    pub fn is_disconnected(&self) -> bool {
+        self.shared.disconnected.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of free slots, up to `wanted`, only reading
+    /// the head if the cached one doesn't leave enough room.
+    fn free_slots(&mut self, wanted: usize) -> usize {
+        let shared = &*self.shared;
+        let mut free = shared.capacity() - shared.distance(self.cached_head, self.tail);
+        if free < wanted {
+            // Acquire matches the Release in `Receiver::publish`, so the
+            // receiver is done with the slots before we reuse them.
+            self.cached_head = shared.head.load(Ordering::Acquire);
+            free = shared.capacity() - shared.distance(self.cached_head, self.tail);
+        }
+        free.min(wanted)
+    }
+
+    fn publish(&mut self, n: usize) {
+        self.tail = self.shared.advance(self.tail, n);
+        // SeqCst also releases the slots to the receiver, see `notify`.
+        self.shared.tail.store(self.tail, Ordering::SeqCst);
+        self.shared.notify(&self.shared.receiver_waiting);
+    }
+}
+
+impl<T: Copy> Sender<T> {
+    /// Sends as many messages from `values` as fit, without waiting.
+    ///
+    /// Returns the number of messages sent.
+    pub fn push_slice(&mut self, values: &[T]) -> usize {
+        if self.is_disconnected() {
+            return 0;
+        }
+        let n = self.free_slots(values.len());
+        if n == 0 {
+            return 0;
+        }
+        let capacity = self.capacity();
+        let start = self.tail % capacity;
+        let first = n.min(capacity - start);
+        // Safety: The `n` slots after the tail are free, and `T: Copy`
+        // means there's nothing to drop.
+        unsafe {
+            ptr::copy_nonoverlapping(values.as_ptr(), self.shared.slot(self.tail), first);
+            ptr::copy_nonoverlapping(values[first..].as_ptr(), self.shared.slot(0), n - first);
+        }
+        self.publish(n);
+        n
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receives a message, waiting for one if the channel is empty.
+    ///
+    /// Returns `None` once the channel is empty and the sender is gone.
+    pub fn pop(&mut self) -> Option<T> {
+        loop {
+            if let Some(value) = self.try_pop() {
+                return Some(value);
+            }
+            if self.shared.disconnected.load(Ordering::Acquire) {
+                // The sender might have pushed right before leaving.
+                return self.try_pop();
+            }
+            let shared = &*self.shared;
+            let head = self.head;
+            shared.wait_while(&shared.receiver_waiting, || {
+                shared.tail.load(Ordering::SeqCst) != head
+            });
+        }
+    }
+
+    /// Receives a message if there is one.
+    pub fn try_pop(&mut self) -> Option<T> {
+        if self.available(1) == 0 {
+            return None;
+        }
+        // Safety: The slot was written by the sender, and only we read it.
+        let value = unsafe { self.shared.slot(self.head).read() };
+        self.publish(1);
+        Some(value)
+    }
+
+    /// Returns the number of messages in the channel.
+    pub fn len(&self) -> usize {
+        self.shared.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity()
+    }
+
+    /// Returns whether the sender is gone.
+    pub fn is_disconnected(&self) -> bool {
+        self.shared.disconnected.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of messages ready, up to `wanted`, only reading
+    /// the tail if the cached one doesn't have enough.
+    fn available(&mut self, wanted: usize) -> usize {
+        let shared = &*self.shared;
+        let mut available = shared.distance(self.head, self.cached_tail);
+        if available < wanted {
+            // Acquire matches the Release in `Sender::publish`.
+            self.cached_tail = shared.tail.load(Ordering::Acquire);
+            available = shared.distance(self.head, self.cached_tail);
+        }
+        available.min(wanted)
+    }
+
+    fn publish(&mut self, n: usize) {
+        self.head = self.shared.advance(self.head, n);
+        // SeqCst also releases the slots to the sender, see `notify`.
+        self.shared.head.store(self.head, Ordering::SeqCst);
+        self.shared.notify(&self.shared.sender_waiting);
+    }
+}
+
+impl<T: Copy> Receiver<T> {
+    /// Receives as many messages into `buf` as are ready, without waiting.
+    ///
+    /// Returns the number of messages received.
+    pub fn pop_slice(&mut self, buf: &mut [T]) -> usize {
+        let n = self.available(buf.len());
+        if n == 0 {
+            return 0;
+        }
+        let capacity = self.capacity();
+        let start = self.head % capacity;
+        let first = n.min(capacity - start);
+        // Safety: The `n` slots after the head were written by the sender.
+        unsafe {
+            ptr::copy_nonoverlapping(self.shared.slot(self.head), buf.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(self.shared.slot(0), buf[first..].as_mut_ptr(), n - first);
+        }
+        self.publish(n);
+        n
+    }
+}
+
+impl<T> Iterator for Receiver<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pop()
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.shared.disconnect();
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.disconnect();
+    }
+}
+
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let mut head = *self.head.get_mut();
+        let tail = *self.tail.get_mut();
+        while head != tail {
+            unsafe { self.slot(head).drop_in_place() };
+            head = self.advance(head, 1);
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn one_thread_should_work() {
+        let (mut s, mut r) = channel(2);
+        assert_eq!(s.try_push(1), Ok(()));
+        assert_eq!(s.try_push(2), Ok(()));
+        assert_eq!(s.try_push(3), Err(3));
+        assert_eq!(r.len(), 2);
+        assert_eq!(r.try_pop(), Some(1));
+        assert_eq!(s.try_push(3), Ok(()));
+        assert_eq!(r.try_pop(), Some(2));
+        assert_eq!(r.try_pop(), Some(3));
+        assert_eq!(r.try_pop(), None);
+        assert!(s.is_empty());
+    }
+
+    #[test]
+    fn blocking_push_pop_should_keep_order() {
+        let (mut s, r) = channel(3);
+        let t = thread::spawn(move || {
+            for i in 0..10000 {
+                s.push(i).unwrap();
+            }
+        });
+        // Ends once the sender is gone and everything is received.
+        assert!(r.eq(0..10000));
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn slices_should_wrap_around() {
+        let (mut s, mut r) = channel(4);
+        let mut buf = [0; 8];
+        assert_eq!(s.push_slice(&[1, 2, 3]), 3);
+        assert_eq!(r.pop_slice(&mut buf[..2]), 2);
+        assert_eq!(buf[..2], [1, 2]);
+        // Only 3 slots are free, starting at the end of the buffer.
+        assert_eq!(s.push_slice(&[4, 5, 6, 7, 8]), 3);
+        assert_eq!(r.pop_slice(&mut buf), 4);
+        assert_eq!(buf[..4], [3, 4, 5, 6]);
+        assert_eq!(r.pop_slice(&mut buf), 0);
+    }
+
+    #[test]
+    fn disconnect_should_be_noticed() {
+        let (mut s, r) = channel(1);
+        // Fill the channel before spawning, so the receiver can't be gone yet.
+        s.push(1).unwrap();
+        let t = thread::spawn(move || {
+            // Either blocks until the receiver is gone, or sees it's gone.
+            assert_eq!(s.push(2), Err(2));
+        });
+        drop(r);
+        t.join().unwrap();
+
+        let (mut s, mut r) = channel(2);
+        s.push(1).unwrap();
+        drop(s);
+        assert_eq!(r.pop(), Some(1));
+        assert_eq!(r.pop(), None);
+    }
+
+    #[test]
+    fn unreceived_messages_should_be_dropped() {
+        let value = Arc::new(());
+        let (mut s, mut r) = channel(4);
+        for _ in 0..3 {
+            s.push(value.clone()).unwrap();
+        }
+        drop(r.pop());
+        drop((s, r));
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
@@ -0,0 +1,368 @@
+use std::{
+    borrow::Borrow,
+    fmt,
+    hash::{Hash, Hasher},
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    sync::atomic::{fence, AtomicUsize, Ordering},
+};
+
+struct StrongArcData<T> {
+    /// Number of `StrongArc`s, or 1 while owned by a `UniqueArc`.
+    ref_count: AtomicUsize,
+    data: T,
+}
+
+/// An `Arc` without weak pointers, so it only needs a single counter.
+///
+/// Cloning and dropping do one atomic operation on that counter, and the
+/// allocation is freed together with the data.
+pub struct StrongArc<T> {
+    ptr: NonNull<StrongArcData<T>>,
+}
+
+unsafe impl<T: Send + Sync> Send for StrongArc<T> {}
+unsafe impl<T: Send + Sync> Sync for StrongArc<T> {}
+
+/// A uniquely owned `StrongArc` allocation.
+///
+/// The data can be mutated freely, then `UniqueArc::freeze` turns it into a
+/// `StrongArc` without touching the counter.
+pub struct UniqueArc<T> {
+    ptr: NonNull<StrongArcData<T>>,
+}
+
+unsafe impl<T: Send> Send for UniqueArc<T> {}
+unsafe impl<T: Sync> Sync for UniqueArc<T> {}
+
+impl<T> StrongArc<T> {
+    pub fn new(data: T) -> StrongArc<T> {
+        UniqueArc::freeze(UniqueArc::new(data))
+    }
+
+    /// Returns the inner value if this is the only `StrongArc`,
+    /// otherwise gives the `StrongArc` back.
+    pub fn try_unwrap(arc: Self) -> Result<T, Self> {
+        StrongArc::try_unique(arc).map(UniqueArc::into_inner)
+    }
+
+    /// Drops this `StrongArc` and returns the inner value if it was the last one.
+    pub fn into_inner(arc: Self) -> Option<T> {
+        let arc = ManuallyDrop::new(arc);
+        if arc.data().ref_count.fetch_sub(1, Ordering::Release) != 1 {
+            return None;
+        }
+        fence(Ordering::Acquire);
+        // Safety: We just dropped the last StrongArc,
+        // so nothing else will access the allocation anymore.
+        let data = unsafe { Box::from_raw(arc.ptr.as_ptr()) };
+        Some(data.data)
+    }
+
+    /// Turns this `StrongArc` back into a `UniqueArc` if it's the only one.
+    pub fn try_unique(arc: Self) -> Result<UniqueArc<T>, Self> {
+        // Acquire to match StrongArc::drop's Release decrement.
+        if arc.data().ref_count.load(Ordering::Acquire) != 1 {
+            return Err(arc);
+        }
+        // No other StrongArc exists, and without weak pointers
+        // none can be created, so the count stays 1.
+        let arc = ManuallyDrop::new(arc);
+        Ok(UniqueArc { ptr: arc.ptr })
+    }
+
+    /// Returns a mutable reference to the data, cloning it first
+    /// if there are other `StrongArc`s sharing it (copy-on-write).
+    pub fn make_mut(arc: &mut Self) -> &mut T
+    where
+        T: Clone,
+    {
+        if arc.data().ref_count.load(Ordering::Acquire) != 1 {
+            *arc = StrongArc::new((**arc).clone());
+        }
+        // Safety: Either we just created a fresh StrongArc,
+        // or we've verified this one is the only pointer to the data.
+        unsafe { &mut (*arc.ptr.as_ptr()).data }
+    }
+
+    pub fn get_mut(arc: &mut Self) -> Option<&mut T> {
+        // Acquire to match StrongArc::drop's Release decrement, to make sure
+        // nothing else is accessing the data.
+        if arc.data().ref_count.load(Ordering::Acquire) != 1 {
+            return None;
+        }
+        unsafe { Some(&mut (*arc.ptr.as_ptr()).data) }
+    }
+
+    /// Gets a raw pointer to the data, without affecting the count.
+    pub fn as_ptr(this: &Self) -> *const T {
+        &this.data().data
+    }
+
+    /// Consumes the `StrongArc` and returns a raw pointer to the data.
+    ///
+    /// The count is kept, use `StrongArc::from_raw` to get the `StrongArc` back.
+    pub fn into_raw(this: Self) -> *const T {
+        let ptr = Self::as_ptr(&this);
+        std::mem::forget(this);
+        ptr
+    }
+
+    /// Constructs a `StrongArc` back from a pointer returned by `StrongArc::into_raw`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `StrongArc::into_raw`, and every `from_raw` must
+    /// match one count owned by the caller.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        let offset = std::mem::offset_of!(StrongArcData<T>, data);
+        StrongArc {
+            ptr: NonNull::new_unchecked(ptr.byte_sub(offset) as *mut StrongArcData<T>),
+        }
+    }
+
+    /// Returns `true` if both `StrongArc`s point to the same allocation.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        this.ptr == other.ptr
+    }
+
+    /// Gets the number of `StrongArc`s pointing to this allocation.
+    pub fn strong_count(this: &Self) -> usize {
+        this.data().ref_count.load(Ordering::Acquire)
+    }
+
+    fn data(&self) -> &StrongArcData<T> {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> UniqueArc<T> {
+    pub fn new(data: T) -> UniqueArc<T> {
+        UniqueArc {
+            ptr: NonNull::from(Box::leak(Box::new(StrongArcData {
+                ref_count: AtomicUsize::new(1),
+                data,
+            }))),
+        }
+    }
+
+    /// Shares the data, turning this into a `StrongArc`.
+    pub fn freeze(this: Self) -> StrongArc<T> {
+        // The count was initialized to 1 and nobody else could
+        // have touched it, so there's nothing to update.
+        let this = ManuallyDrop::new(this);
+        StrongArc { ptr: this.ptr }
+    }
+
+    pub fn into_inner(this: Self) -> T {
+        let this = ManuallyDrop::new(this);
+        // Safety: We are the only owner of the allocation.
+        let data = unsafe { Box::from_raw(this.ptr.as_ptr()) };
+        data.data
+    }
+}
+
+impl<T> Deref for StrongArc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.data().data
+    }
+}
+
+impl<T> Deref for UniqueArc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &self.ptr.as_ref().data }
+    }
+}
+
+impl<T> DerefMut for UniqueArc<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: Nothing else points to the allocation.
+        unsafe { &mut self.ptr.as_mut().data }
+    }
+}
+
+impl<T> Clone for StrongArc<T> {
+    fn clone(&self) -> Self {
+        if self.data().ref_count.fetch_add(1, Ordering::Relaxed) > usize::MAX / 2 {
+            std::process::abort();
+        }
+        StrongArc { ptr: self.ptr }
+    }
+}
+
+impl<T> Drop for StrongArc<T> {
+    fn drop(&mut self) {
+        if self.data().ref_count.fetch_sub(1, Ordering::Release) == 1 {
+            fence(Ordering::Acquire);
+            // Safety: The reference counter is zero,
+            // so nothing will access the allocation anymore.
+            unsafe {
+                drop(Box::from_raw(self.ptr.as_ptr()));
+            }
+        }
+    }
+}
+
+impl<T> Drop for UniqueArc<T> {
+    fn drop(&mut self) {
+        unsafe {
+            drop(Box::from_raw(self.ptr.as_ptr()));
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for StrongArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for StrongArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for UniqueArc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: PartialEq> PartialEq for StrongArc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: Eq> Eq for StrongArc<T> {}
+
+impl<T: Hash> Hash for StrongArc<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl<T> Borrow<T> for StrongArc<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T> AsRef<T> for StrongArc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T> From<T> for StrongArc<T> {
+    fn from(data: T) -> Self {
+        StrongArc::new(data)
+    }
+}
+
+impl<T> From<UniqueArc<T>> for StrongArc<T> {
+    fn from(unique: UniqueArc<T>) -> Self {
+        UniqueArc::freeze(unique)
+    }
+}
+
+impl<T: Default> Default for StrongArc<T> {
+    fn default() -> Self {
+        StrongArc::new(T::default())
+    }
+}
+
+impl<T: Default> Default for UniqueArc<T> {
+    fn default() -> Self {
+        UniqueArc::new(T::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn strong_arc_should_work() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop;
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let x = StrongArc::new(("hello", DetectDrop));
+        let y = x.clone();
+        assert_eq!(StrongArc::strong_count(&x), 2);
+        assert!(StrongArc::ptr_eq(&x, &y));
+
+        let t = thread::spawn(move || {
+            assert_eq!(y.0, "hello");
+        });
+        t.join().unwrap();
+
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 0);
+        assert_eq!(StrongArc::strong_count(&x), 1);
+        drop(x);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn unique_arc_should_work() {
+        let mut unique = UniqueArc::new(Vec::new());
+        unique.push(1);
+        unique.push(2);
+
+        let frozen = UniqueArc::freeze(unique);
+        let other = frozen.clone();
+        assert_eq!(*other, [1, 2]);
+
+        // Can't go back while shared.
+        let frozen = StrongArc::try_unique(frozen).unwrap_err();
+        drop(other);
+        let mut unique = StrongArc::try_unique(frozen).unwrap();
+        unique.push(3);
+        assert_eq!(UniqueArc::into_inner(unique), [1, 2, 3]);
+    }
+
+    #[test]
+    fn try_unwrap_and_into_inner_should_work() {
+        let x = StrongArc::new(String::from("hello"));
+        let y = x.clone();
+        let x = StrongArc::try_unwrap(x).unwrap_err();
+        assert_eq!(StrongArc::into_inner(y), None);
+        assert_eq!(StrongArc::try_unwrap(x).unwrap(), "hello");
+    }
+
+    #[test]
+    fn make_mut_should_clone_on_write() {
+        let mut x = StrongArc::new(1);
+        let y = x.clone();
+        assert!(StrongArc::get_mut(&mut x).is_none());
+        *StrongArc::make_mut(&mut x) += 1;
+        assert_eq!((*x, *y), (2, 1));
+        assert!(!StrongArc::ptr_eq(&x, &y));
+        *StrongArc::get_mut(&mut x).unwrap() += 1;
+        assert_eq!(*x, 3);
+    }
+
+    #[test]
+    fn raw_pointer_round_trip_should_work() {
+        let x = StrongArc::new((1u8, 2u64));
+        let ptr = StrongArc::into_raw(x.clone());
+        assert_eq!(unsafe { (*ptr).1 }, 2);
+        let y = unsafe { StrongArc::from_raw(ptr) };
+        assert!(StrongArc::ptr_eq(&x, &y));
+        drop(y);
+        assert_eq!(StrongArc::strong_count(&x), 1);
+    }
+}
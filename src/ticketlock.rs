@@ -0,0 +1,152 @@
+use std::{
+    cell::UnsafeCell,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::backoff::Backoff;
+
+/// A fair spin lock: threads get the lock in the order they asked for it.
+pub struct TicketLock<T: ?Sized> {
+    /// The ticket the next thread will get.
+    next_ticket: AtomicU32,
+    /// The ticket of the thread holding the lock.
+    now_serving: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+pub struct TicketLockGuard<'a, T: ?Sized> {
+    lock: &'a TicketLock<T>,
+}
+
+unsafe impl<T: ?Sized> Sync for TicketLock<T> where T: Send {}
+
+impl<T> TicketLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            next_ticket: AtomicU32::new(0),
+            now_serving: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+}
+
+impl<T: ?Sized> TicketLock<T> {
+    pub fn lock(&self) -> TicketLockGuard<'_, T> {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        let mut backoff = Backoff::new();
+        // Acquire matches the Release increment in `drop`.
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            backoff.snooze();
+        }
+        TicketLockGuard { lock: self }
+    }
+
+    /// Tries to acquire the lock without spinning.
+    pub fn try_lock(&self) -> Option<TicketLockGuard<'_, T>> {
+        // Only take a ticket if it would be served right away.
+        let serving = self.now_serving.load(Ordering::Relaxed);
+        self.next_ticket
+            .compare_exchange(
+                serving,
+                serving.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .ok()
+            .map(|_| TicketLockGuard { lock: self })
+    }
+}
+
+impl<T: ?Sized> Drop for TicketLockGuard<'_, T> {
+    fn drop(&mut self) {
+        // Only the lock holder changes `now_serving`.
+        self.lock.now_serving.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl<T: ?Sized> Deref for TicketLockGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Safety: The guard means we hold the lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for TicketLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for TicketLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("TicketLock");
+        // Never spin in `Debug`, the lock might be held by the caller.
+        match self.try_lock() {
+            Some(guard) => d.field("data", &&*guard),
+            None => d.field("data", &format_args!("<locked>")),
+        };
+        d.finish_non_exhaustive()
+    }
+}
+
+impl<T: Default> Default for TicketLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for TicketLock<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for TicketLockGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for TicketLockGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn multi_threads_should_work() {
+        let lock = TicketLock::new(0);
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *lock.lock() += 1;
+                    }
+                });
+            }
+        });
+        assert_eq!(*lock.lock(), 4000);
+    }
+
+    #[test]
+    fn try_lock_and_debug_should_work() {
+        let lock = TicketLock::new(1);
+        assert_eq!(format!("{lock:?}"), "TicketLock { data: 1, .. }");
+        let guard = lock.try_lock().unwrap();
+        assert!(lock.try_lock().is_none());
+        assert_eq!(format!("{lock:?}"), "TicketLock { data: <locked>, .. }");
+        drop(guard);
+        // A failed try_lock must not take a ticket.
+        assert_eq!(*lock.lock(), 1);
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    fmt,
+    marker::PhantomData,
+    mem::ManuallyDrop,
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use crate::epoch;
+
+/// A lock-free LIFO stack.
+///
+/// Popped nodes are freed through the crate's epoch-based reclamation, so
+/// a node can't be freed (or reused by the allocator, which would cause ABA
+/// problems) while another thread might still be looking at it.
+pub struct TreiberStack<T> {
+    head: AtomicPtr<Node<T>>,
+    _marker: PhantomData<T>,
+}
+
+struct Node<T> {
+    /// Moved out by the thread that pops the node.
+    value: ManuallyDrop<T>,
+    next: *mut Node<T>,
+}
+
+unsafe impl<T: Send> Send for TreiberStack<T> {}
+unsafe impl<T: Send> Sync for TreiberStack<T> {}
+
+impl<T> TreiberStack<T> {
+    pub const fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn push(&self, value: T) {
+        let node = Box::into_raw(Box::new(Node {
+            value: ManuallyDrop::new(value),
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            // Safety: The node isn't shared until the CAS succeeds.
+            unsafe { (*node).next = head };
+            // Release publishes the node to the popping thread.
+            match self
+                .head
+                .compare_exchange_weak(head, node, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return,
+                Err(e) => head = e,
+            }
+        }
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        let guard = epoch::pin();
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            if head.is_null() {
+                return None;
+            }
+            // Safety: We're pinned, so the node can't have been freed even
+            // if another thread popped it in the meantime.
+            let next = unsafe { (*head).next };
+            match self
+                .head
+                .compare_exchange_weak(head, next, Ordering::Acquire, Ordering::Acquire)
+            {
+                Ok(_) => unsafe {
+                    // Only the thread that unlinked the node reads the value.
+                    let value = ManuallyDrop::take(&mut (*head).value);
+                    guard.defer_destroy(head);
+                    return Some(value);
+                },
+                Err(e) => head = e,
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+}
+
+impl<T> Drop for TreiberStack<T> {
+    fn drop(&mut self) {
+        let mut node = *self.head.get_mut();
+        while !node.is_null() {
+            // Safety: We have exclusive access, and popped
+            // nodes aren't in the list anymore.
+            let mut boxed = unsafe { Box::from_raw(node) };
+            unsafe { ManuallyDrop::drop(&mut boxed.value) };
+            node = boxed.next;
+        }
+    }
+}
+
+impl<T> Default for TreiberStack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for TreiberStack<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TreiberStack")
+            .field("is_empty", &self.is_empty())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    use super::*;
+
+    #[test]
+    fn one_thread_should_work() {
+        let stack = TreiberStack::new();
+        assert!(stack.is_empty());
+        stack.push(1);
+        stack.push(2);
+        assert!(!stack.is_empty());
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(stack.pop(), Some(1));
+        assert_eq!(stack.pop(), None);
+    }
+
+    #[test]
+    fn concurrent_push_pop_should_not_lose_or_duplicate() {
+        const THREADS: usize = 4;
+        const PER_THREAD: usize = 5000;
+        let stack = TreiberStack::new();
+        let sum = AtomicUsize::new(0);
+        let popped = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for t in 0..THREADS {
+                let (stack, sum, popped) = (&stack, &sum, &popped);
+                s.spawn(move || {
+                    for i in 0..PER_THREAD {
+                        stack.push(t * PER_THREAD + i);
+                        // Pop about as often as we push, to keep reusing nodes.
+                        if let Some(v) = stack.pop() {
+                            sum.fetch_add(v, Ordering::Relaxed);
+                            popped.fetch_add(1, Ordering::Relaxed);
+                        }
+                    }
+                });
+            }
+        });
+        while let Some(v) = stack.pop() {
+            sum.fetch_add(v, Ordering::Relaxed);
+            popped.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let n = THREADS * PER_THREAD;
+        assert_eq!(popped.load(Ordering::Relaxed), n);
+        assert_eq!(sum.load(Ordering::Relaxed), n * (n - 1) / 2);
+    }
+
+    #[test]
+    fn drop_should_drop_remaining_values() {
+        static NUM_DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct DetectDrop;
+        impl Drop for DetectDrop {
+            fn drop(&mut self) {
+                NUM_DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let stack = TreiberStack::new();
+        for _ in 0..3 {
+            stack.push(DetectDrop);
+        }
+        drop(stack.pop());
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 1);
+        drop(stack);
+        assert_eq!(NUM_DROPS.load(Ordering::Relaxed), 3);
+    }
+}